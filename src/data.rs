@@ -4,14 +4,20 @@ use crossbeam_channel::Sender;
 use druid::im::Vector;
 use druid::piet::{PietTextLayoutBuilder, TextStorage as PietTextStorage};
 use druid::text::{RichText, TextStorage};
+use druid::Application;
 use druid::Data;
 use druid::Env;
 use druid::EventCtx;
 use druid::Lens;
 use serde::{Deserialize, Serialize};
 
-use crate::delegate::{SEARCH, SET_VIEW};
+use crate::delegate::{
+    FOLLOW, LEVEL_FILTER, PATTERN_FILTER, RESTART_STREAM, SEARCH, SET_VIEW, STOP_STREAM,
+    TREE_QUERY, TREE_TOGGLE,
+};
+use crate::export;
 use crate::index::CommandMessage;
+use crate::level::Level;
 use crate::GLOBAL_STATE;
 
 #[derive(Clone, Data, Lens)]
@@ -21,9 +27,29 @@ pub struct AppState {
     pub viewlimit: f64,
     pub not_query: String,
     pub exact: bool,
+    pub collapse: bool,
+    /// Results as a table with a column per checked pointer.
+    pub table: bool,
+    pub columns: Vector<Column>,
     pub items: Vector<Item>,
     pub items_rich: Vector<ItemRich>,
+    /// The result moved to with the keyboard.
+    #[data(ignore)]
+    pub selected: Option<usize>,
+    pub export_info: String,
     pub view: String,
+    /// The document in the detail pane, a row per field.
+    pub tree: Vector<TreeNode>,
+    /// The leaves of the document in the detail pane, to follow.
+    pub view_pointers: Vector<ViewPointer>,
+    pub follow: Vector<FollowGroup>,
+    pub follow_info: String,
+    /// The ingest order number of the document in the detail pane.
+    #[data(ignore)]
+    pub view_number: Option<u64>,
+    pub context_size: f64,
+    pub context: Vector<Item>,
+    pub context_info: String,
     pub pointers: Vector<PointerState>,
     pub pointers_view: Vector<PointerState>,
     pub query_time: String,
@@ -36,6 +62,25 @@ pub struct AppState {
     pub ongoing_search: bool,
     pub properties: Vector<String>,
     pub tail: bool,
+    pub contexts: Vector<KubeContext>,
+    pub file_pattern: String,
+    pub show_streams: bool,
+    pub streams: Vector<StreamStatus>,
+    pub show_patterns: bool,
+    pub patterns: Vector<PatternStatus>,
+    pub patterns_info: String,
+    pub anomalies: Vector<AnomalyStatus>,
+    pub anomaly_since: String,
+    pub pattern_filter: String,
+    /// A chip per level, unticked levels are filtered away.
+    pub levels: Vector<LevelChip>,
+    pub level_pointer: String,
+    pub ingest_config: String,
+    pub ingest_error: String,
+    pub ingest_sample: String,
+    /// The sample is a stored JSON document the rules have already run on.
+    pub ingest_sample_processed: bool,
+    pub ingest_preview: String,
     #[data(ignore)]
     pub tx: Sender<CommandMessage>,
 }
@@ -43,8 +88,7 @@ pub struct AppState {
 impl AppState {
     pub fn persist(&mut self) {
         let parameters = self.get_serializable_parameters();
-        let serialized: Vec<u8> = bincode::serialize(&parameters).unwrap();
-        fs::write(".melt_state.dat", serialized).unwrap();
+        fs::write(".melt_state.dat", parameters.to_bytes()).unwrap();
     }
     fn get_serializable_parameters(&self) -> SerializableParameters {
         SerializableParameters {
@@ -59,6 +103,7 @@ impl AppState {
                 .map(|p| p.clone())
                 .collect::<Vec<PointerState>>(),
             sort: GLOBAL_STATE.lock().unwrap().sort.to_string(),
+            level_pointer: self.level_pointer.to_string(),
         }
     }
 }
@@ -68,6 +113,7 @@ pub struct SerializableParameters {
     pub pointer_state: Vec<PointerState>,
     pub pointer_state_view: Vec<PointerState>,
     pub sort: String,
+    pub level_pointer: String,
 }
 
 impl Default for SerializableParameters {
@@ -76,6 +122,78 @@ impl Default for SerializableParameters {
             pointer_state: vec![],
             pointer_state_view: vec![],
             sort: "".to_string(),
+            level_pointer: "".to_string(),
+        }
+    }
+}
+
+/// Starts `.melt_state.dat` files written with a version, files without it have
+/// the layout from before `checked_volatile` and `level_pointer`.
+const STATE_MAGIC: [u8; 4] = *b"MELT";
+const STATE_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct LegacyParameters {
+    pointer_state: Vec<LegacyPointerState>,
+    pointer_state_view: Vec<LegacyPointerState>,
+    sort: String,
+}
+
+#[derive(Deserialize)]
+struct LegacyPointerState {
+    text: String,
+    number: u64,
+    checked: bool,
+    checked_sort: bool,
+}
+
+impl From<LegacyPointerState> for PointerState {
+    fn from(p: LegacyPointerState) -> Self {
+        PointerState {
+            text: p.text,
+            number: p.number,
+            checked: p.checked,
+            checked_sort: p.checked_sort,
+            checked_volatile: false,
+        }
+    }
+}
+
+impl SerializableParameters {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = STATE_MAGIC.to_vec();
+        bytes.extend(STATE_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    /// Reads the current layout and migrates the one without a version.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes.strip_prefix(&STATE_MAGIC) {
+            Some(rest) if rest.len() >= 4 => {
+                match u32::from_le_bytes(rest[..4].try_into().unwrap()) {
+                    STATE_VERSION => bincode::deserialize(&rest[4..]).ok(),
+                    _ => None,
+                }
+            }
+            Some(_) => None,
+            None => {
+                let legacy = bincode::deserialize::<LegacyParameters>(bytes).ok()?;
+                Some(SerializableParameters {
+                    pointer_state: legacy
+                        .pointer_state
+                        .into_iter()
+                        .map(PointerState::from)
+                        .collect(),
+                    pointer_state_view: legacy
+                        .pointer_state_view
+                        .into_iter()
+                        .map(PointerState::from)
+                        .collect(),
+                    sort: legacy.sort,
+                    level_pointer: "".to_string(),
+                })
+            }
         }
     }
 }
@@ -86,6 +204,119 @@ pub struct PointerState {
     pub number: u64,
     pub checked: bool,
     pub checked_sort: bool,
+    /// Ignored when collapsing equal documents, timestamps and request ids.
+    pub checked_volatile: bool,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct Column {
+    pub pointer: String,
+    pub width: f64,
+    /// Characters shown before the value is cut, 0 wraps the value instead.
+    pub truncate: f64,
+    pub sorted: bool,
+}
+
+/// A column per checked pointer in the order they were checked, keeping the
+/// width and truncation of the columns already there.
+pub fn columns(pointers: &Vector<PointerState>, previous: &Vector<Column>) -> Vector<Column> {
+    let mut checked = pointers
+        .iter()
+        .filter(|p| p.checked)
+        .collect::<Vec<&PointerState>>();
+    checked.sort_by_key(|p| p.number);
+    checked
+        .into_iter()
+        .map(|p| match previous.iter().find(|c| c.pointer == p.text) {
+            Some(c) => Column {
+                sorted: p.checked_sort,
+                ..c.clone()
+            },
+            None => Column {
+                pointer: p.text.to_string(),
+                width: 200.0,
+                truncate: 60.0,
+                sorted: p.checked_sort,
+            },
+        })
+        .collect()
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct KubeContext {
+    pub name: String,
+    pub checked: bool,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct StreamStatus {
+    pub key: String,
+    pub state: String,
+    pub lines: String,
+    pub bytes: String,
+    pub dropped: String,
+    pub last_line: String,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct LevelChip {
+    pub level: Level,
+    /// Matches with the level among the documents the last search looked at.
+    pub count: String,
+    pub checked: bool,
+}
+
+pub fn level_chips() -> Vector<LevelChip> {
+    Level::ALL
+        .iter()
+        .map(|level| LevelChip {
+            level: *level,
+            count: "0".to_string(),
+            checked: true,
+        })
+        .collect()
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct PatternStatus {
+    pub template: String,
+    pub count: String,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct TreeNode {
+    pub pointer: String,
+    pub depth: usize,
+    /// The value as text, pretty JSON for objects and arrays.
+    pub value: String,
+    /// The key and value colored by type with the query highlighted.
+    pub text: RichText,
+    pub container: bool,
+    pub expanded: bool,
+    /// Under a collapsed object or array.
+    pub hidden: bool,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct ViewPointer {
+    pub pointer: String,
+    pub value: String,
+}
+
+/// The documents of one service holding a followed id.
+#[derive(Clone, Data, Lens)]
+pub struct FollowGroup {
+    pub name: String,
+    pub items: Vector<Item>,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct AnomalyStatus {
+    pub template: String,
+    /// `new` or how many times faster.
+    pub change: String,
+    pub before: String,
+    pub after: String,
 }
 
 #[derive(Clone, Data, Lens, Serialize, Deserialize)]
@@ -96,6 +327,127 @@ pub struct PointerStateItem {
 }
 
 impl AppState {
+    pub fn selected_contexts(&self) -> Vec<String> {
+        self.contexts
+            .iter()
+            .filter(|c| c.checked)
+            .map(|c| c.name.to_string())
+            .collect()
+    }
+
+    pub fn sync_columns(&mut self) {
+        self.columns = columns(&self.pointers, &self.columns);
+    }
+
+    /// Sorts the documents by the pointer, or by nothing when empty.
+    pub fn sort_by(&mut self, pointer: &str) {
+        self.pointers
+            .iter_mut()
+            .for_each(|p| p.checked_sort = !pointer.is_empty() && p.text == pointer);
+        GLOBAL_STATE.lock().unwrap().sort = pointer.to_string();
+        self.tx.send(CommandMessage::RESORT).unwrap();
+        self.sync_columns();
+    }
+
+    /// Swaps the column with the one before or after it.
+    pub fn move_column(&mut self, i: usize, after: bool) {
+        let j = match after {
+            true if i + 1 < self.columns.len() => i + 1,
+            false if i > 0 => i - 1,
+            _ => return,
+        };
+        let (a, b) = (
+            self.columns[i].pointer.clone(),
+            self.columns[j].pointer.clone(),
+        );
+        let numbers = self
+            .pointers
+            .iter()
+            .filter(|p| p.text == a || p.text == b)
+            .map(|p| p.number)
+            .collect::<Vec<u64>>();
+        if let [x, y] = numbers[..] {
+            self.pointers.iter_mut().for_each(|p| {
+                if p.text == a || p.text == b {
+                    p.number = if p.number == x { y } else { x };
+                }
+            });
+        }
+        self.columns.swap(i, j);
+    }
+
+    /// Moves the selection by `step` results, clamped to the first and last,
+    /// and shows the selected document in the detail pane.
+    pub fn move_selection(&mut self, ctx: &mut EventCtx, step: isize) {
+        if self.items_rich.is_empty() {
+            return;
+        }
+        let last = self.items_rich.len() as isize - 1;
+        let i = match self.selected {
+            Some(i) => (i as isize + step).clamp(0, last),
+            None if step < 0 => last,
+            None => 0,
+        } as usize;
+        if let Some(previous) = self.selected.and_then(|p| self.items_rich.get_mut(p)) {
+            previous.selected = false;
+        }
+        self.selected = Some(i);
+        let item = &mut self.items_rich[i];
+        item.selected = true;
+        ctx.submit_command(SET_VIEW.with((item.text.to_string(), item.number)));
+    }
+
+    /// The ticked results, or the selected one when none are ticked.
+    pub fn marked(&self) -> Vec<String> {
+        let checked = self
+            .items_rich
+            .iter()
+            .filter(|i| i.checked)
+            .map(|i| i.text.to_string())
+            .collect::<Vec<String>>();
+        match checked.is_empty() {
+            true => self
+                .selected
+                .and_then(|i| self.items_rich.get(i))
+                .map(|i| vec![i.text.to_string()])
+                .unwrap_or_default(),
+            false => checked,
+        }
+    }
+
+    /// The checked view pointers in the order they were checked.
+    pub fn view_columns(&self) -> Vec<String> {
+        let mut checked = self
+            .pointers_view
+            .iter()
+            .filter(|p| p.checked)
+            .collect::<Vec<&PointerState>>();
+        checked.sort_by_key(|p| p.number);
+        checked.into_iter().map(|p| p.text.to_string()).collect()
+    }
+
+    pub fn check_all(&mut self, checked: bool) {
+        self.items_rich.iter_mut().for_each(|i| i.checked = checked);
+    }
+
+    pub fn click_copy_lines(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        Application::global()
+            .clipboard()
+            .put_string(data.marked().join("\n"));
+    }
+
+    pub fn click_copy_pretty(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        Application::global()
+            .clipboard()
+            .put_string(export::pretty(&data.marked()));
+    }
+
+    pub fn click_copy_tsv(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        Application::global()
+            .clipboard()
+            .put_string(export::tsv(&data.marked(), &data.view_columns()));
+    }
+
     pub fn click_search(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
         ctx.submit_command(SEARCH.with((
             (data.query.to_string(), data.not_query.to_string()),
@@ -112,6 +464,13 @@ pub struct Item {
     #[data(ignore)]
     pub pointer_states: Vec<PointerStateItem>,
     pub view: String,
+    #[data(ignore)]
+    pub number: Option<u64>,
+    pub level: Option<Level>,
+    /// Documents collapsed into this one, 1 unless collapsing.
+    pub count: usize,
+    pub first_seen: String,
+    pub last_seen: String,
 }
 
 #[derive(Clone, Data, Lens)]
@@ -122,6 +481,12 @@ pub struct ItemRich {
     #[data(ignore)]
     pub pointer_states: Vec<PointerStateItem>,
     pub view: RichText,
+    #[data(ignore)]
+    pub number: Option<u64>,
+    pub level: Option<Level>,
+    pub selected: bool,
+    /// Ticked to copy.
+    pub checked: bool,
 }
 
 impl PietTextStorage for ItemRich {
@@ -143,15 +508,123 @@ impl Item {
             pointers: Default::default(),
             pointer_states: vec![],
             view: "".to_string(),
+            number: None,
+            level: None,
+            count: 1,
+            first_seen: "".to_string(),
+            last_seen: "".to_string(),
         }
     }
 
     pub fn click_view(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
-        ctx.submit_command(SET_VIEW.with(data.text.to_string()));
+        ctx.submit_command(SET_VIEW.with((data.text.to_string(), data.number)));
     }
 }
+impl StreamStatus {
+    pub fn click_stop(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(STOP_STREAM.with(data.key.to_string()));
+    }
+
+    pub fn click_restart(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(RESTART_STREAM.with(data.key.to_string()));
+    }
+}
+
+impl LevelChip {
+    pub fn click_toggle(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        data.checked = !data.checked;
+        ctx.submit_command(LEVEL_FILTER.with((data.level, data.checked)));
+    }
+}
+
+impl PatternStatus {
+    pub fn click_filter(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(PATTERN_FILTER.with((data.template.to_string(), false)));
+    }
+
+    pub fn click_exclude(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(PATTERN_FILTER.with((data.template.to_string(), true)));
+    }
+}
+
+impl TreeNode {
+    pub fn click_toggle(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        if data.container {
+            ctx.submit_command(TREE_TOGGLE.with(data.pointer.to_string()));
+        }
+    }
+
+    pub fn click_copy_value(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        Application::global().clipboard().put_string(&data.value);
+    }
+
+    pub fn click_copy_pointer(_ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        Application::global().clipboard().put_string(&data.pointer);
+    }
+
+    pub fn click_filter(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(TREE_QUERY.with((data.value.to_string(), false)));
+    }
+
+    pub fn click_exclude(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(TREE_QUERY.with((data.value.to_string(), true)));
+    }
+}
+
+impl ViewPointer {
+    pub fn click_follow(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(FOLLOW.with((data.pointer.to_string(), data.value.to_string())));
+    }
+}
+
+impl AnomalyStatus {
+    pub fn click_filter(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
+        ctx.submit_command(PATTERN_FILTER.with((data.template.to_string(), false)));
+    }
+}
+
 impl ItemRich {
     pub fn click_view(ctx: &mut EventCtx, data: &mut Self, _env: &Env) {
-        ctx.submit_command(SET_VIEW.with(data.text.as_str().to_string()));
+        ctx.submit_command(SET_VIEW.with((data.text.as_str().to_string(), data.number)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Baseline {
+        pointer_state: Vec<(String, u64, bool, bool)>,
+        pointer_state_view: Vec<(String, u64, bool, bool)>,
+        sort: String,
+    }
+
+    #[test]
+    fn migrates_settings_without_a_version() {
+        let baseline = bincode::serialize(&Baseline {
+            pointer_state: vec![("/level".to_string(), 3, true, false)],
+            pointer_state_view: vec![],
+            sort: "/time".to_string(),
+        })
+        .unwrap();
+        let parameters = SerializableParameters::from_bytes(&baseline).unwrap();
+        assert_eq!(parameters.pointer_state[0].text, "/level");
+        assert_eq!(parameters.pointer_state[0].number, 3);
+        assert!(parameters.pointer_state[0].checked);
+        assert!(!parameters.pointer_state[0].checked_volatile);
+        assert_eq!(parameters.sort, "/time");
+        assert_eq!(parameters.level_pointer, "");
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let parameters = SerializableParameters {
+            level_pointer: "/lvl".to_string(),
+            ..SerializableParameters::default()
+        };
+        let read = SerializableParameters::from_bytes(&parameters.to_bytes()).unwrap();
+        assert_eq!(read.level_pointer, "/lvl");
+        assert!(SerializableParameters::from_bytes(b"MELT\x09\0\0\0").is_none());
     }
 }
@@ -2,57 +2,88 @@ use std::collections::VecDeque;
 
 use druid::im::Vector;
 use druid::text::RichTextBuilder;
-use druid::{AppDelegate, Color, Command, DelegateCtx, Env, FontWeight, Handled, Selector, Target};
+use druid::{
+    commands, AppDelegate, Color, Command, DelegateCtx, Env, FontWeight, Handled, Selector, Target,
+};
 use jsonptr::{Pointer, ResolveMut};
 use serde_json::Value;
 
-use crate::data::{AppState, ItemRich, PointerState};
+use crate::data::{AppState, ItemRich, PointerState, TreeNode, ViewPointer};
 use crate::index::CommandMessage;
-use crate::index::CommandMessage::RESORT;
+use crate::ingest;
+use crate::level::Level;
 use crate::GLOBAL_STATE;
 
-pub const SET_VIEW: Selector<String> = Selector::new("set_view");
+/// A document and its number in the ingest order of its source.
+pub const SET_VIEW: Selector<(String, Option<u64>)> = Selector::new("set_view");
 pub const SEARCH: Selector<((String, String), bool)> = Selector::new("search");
 pub const CHECK_CLICKED_FOR_POINTER: Selector<PointerState> = Selector::new("clicked");
 pub const CHECK_CLICKED_FOR_POINTER_SORT: Selector<PointerState> = Selector::new("clicked_sort");
+pub const CHECK_CLICKED_FOR_POINTER_VOLATILE: Selector<PointerState> =
+    Selector::new("clicked_volatile");
 pub const CHECK_CLICKED_FOR_POINTER_VIEW: Selector<PointerState> = Selector::new("clicked_view");
 pub const CHANGE_SETTINGS: Selector<bool> = Selector::new("change_setting");
 pub const SEARCH_RESULT: Selector = Selector::new("search_result");
 pub const CLEAR_DB: Selector = Selector::new("clear_db");
 pub const TAIL: Selector<bool> = Selector::new("tail");
+pub const STOP_STREAM: Selector<String> = Selector::new("stop_stream");
+pub const RESTART_STREAM: Selector<String> = Selector::new("restart_stream");
+/// A template to limit searches to, or exclude when true, empty clears it.
+pub const PATTERN_FILTER: Selector<(String, bool)> = Selector::new("pattern_filter");
+/// A level and whether to show it.
+pub const LEVEL_FILTER: Selector<(Level, bool)> = Selector::new("level_filter");
+/// Expands or collapses the object or array at the pointer in the detail pane.
+pub const TREE_TOGGLE: Selector<String> = Selector::new("tree_toggle");
+/// A value to add to the query, or to the query filtering away when true.
+pub const TREE_QUERY: Selector<(String, bool)> = Selector::new("tree_query");
+/// A pointer of the document in the detail pane and its value to follow.
+pub const FOLLOW: Selector<(String, String)> = Selector::new("follow");
 
 pub struct Delegate;
 
 impl AppDelegate<AppState> for Delegate {
     fn command(
         &mut self,
-        _ctx: &mut DelegateCtx,
+        ctx: &mut DelegateCtx,
         _target: Target,
         cmd: &Command,
         data: &mut AppState,
         _env: &Env,
     ) -> Handled {
-        if let Some(text) = cmd.get(SET_VIEW) {
+        if let Some((text, number)) = cmd.get(SET_VIEW) {
+            data.view_number = *number;
+            let (sample, processed) = ingest::sample(text);
+            data.ingest_sample = sample;
+            data.ingest_sample_processed = processed;
+            data.view_pointers = view_pointers(text);
+            // Documents that are not JSON have no pointers to offer
             if data.pointers.is_empty() {
-                generate_pointers(&serde_json::from_str(&text.as_str()).unwrap())
-                    .iter()
-                    .for_each(|v| {
+                if let Ok(json @ Value::Object(_)) = serde_json::from_str::<Value>(text) {
+                    generate_pointers(&json).iter().for_each(|v| {
                         data.pointers.push_back(PointerState {
                             text: v.to_string(),
                             number: u64::MAX,
                             checked: false,
                             checked_sort: false,
+                            checked_volatile: false,
                         });
                         data.pointers_view.push_back(PointerState {
                             text: v.to_string(),
                             number: u64::MAX,
                             checked: false,
                             checked_sort: false,
+                            checked_volatile: false,
                         });
                     });
+                }
             }
+            let words = match data.exact {
+                true => vec![data.query.as_str()],
+                false => data.query.split_whitespace().collect::<Vec<&str>>(),
+            };
             if data.pointers_view.iter().filter(|p| p.checked).count() == 0 {
-                data.view = parse_json(&text.to_string())
+                data.view = parse_json(&text.to_string());
+                data.tree = tree(text, &words);
             } else {
                 data.pointers_view
                     .sort_by(|left, right| left.number.partial_cmp(&right.number).unwrap());
@@ -63,11 +94,75 @@ impl AppDelegate<AppState> for Delegate {
                     .filter(|p| p.checked)
                     .map(|p| resolve_pointer(&text, &p.text))
                     .collect::<Vec<String>>()
-                    .join(" ")
+                    .join(" ");
+                data.tree = data
+                    .pointers_view
+                    .iter()
+                    .filter(|p| p.checked)
+                    .map(|p| {
+                        let value = resolve_pointer(&text, &p.text);
+                        node(&p.text, &p.text, 0, value, Kind::String, &words)
+                    })
+                    .collect();
             }
             Handled::Yes
+        } else if let Some(pointer) = cmd.get(TREE_TOGGLE) {
+            data.tree.iter_mut().for_each(|n| {
+                if &n.pointer == pointer {
+                    n.expanded = !n.expanded;
+                }
+            });
+            let mut collapsed: Option<usize> = None;
+            data.tree.iter_mut().for_each(|n| {
+                match collapsed {
+                    Some(depth) if n.depth > depth => {
+                        n.hidden = true;
+                        return;
+                    }
+                    _ => collapsed = None,
+                }
+                n.hidden = false;
+                if n.container && !n.expanded {
+                    collapsed = Some(n.depth);
+                }
+            });
+            Handled::Yes
+        } else if let Some((value, exclude)) = cmd.get(TREE_QUERY) {
+            let query = match exclude {
+                true => &mut data.not_query,
+                false => &mut data.query,
+            };
+            *query = match query.trim().is_empty() {
+                true => value.to_string(),
+                false => format!("{} {}", query.trim_end(), value),
+            };
+            {
+                let mut state = GLOBAL_STATE.lock().unwrap();
+                state.query = data.query.to_string();
+                state.query_neg = data.not_query.to_string();
+            }
+            ctx.submit_command(SEARCH.with((
+                (data.query.to_string(), data.not_query.to_string()),
+                data.exact,
+            )));
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
+            data.export_info = "Exporting...".to_string();
+            data.tx
+                .send(CommandMessage::Export(
+                    data.query.to_string(),
+                    data.not_query.to_string(),
+                    data.exact,
+                    data.view_columns(),
+                    file_info.path().to_path_buf(),
+                ))
+                .unwrap();
+            Handled::Yes
         } else if let Some(b) = cmd.get(CHANGE_SETTINGS) {
             data.settings = *b;
+            if !b {
+                GLOBAL_STATE.lock().unwrap().level_pointer = data.level_pointer.to_string();
+            }
             Handled::Yes
         } else if let Some(_) = cmd.get(SEARCH_RESULT) {
             let mut vec1 = vec![];
@@ -94,8 +189,30 @@ impl AppDelegate<AppState> for Delegate {
                     pointers: x.pointers,
                     pointer_states: x.pointer_states,
                     view: builder.build(),
+                    number: x.number,
+                    level: x.level,
+                    selected: false,
+                    checked: false,
                 })
             }
+            // Keep the ticks on the documents still found
+            data.items_rich
+                .iter()
+                .filter(|i| i.checked)
+                .for_each(|item| {
+                    vec1.iter_mut()
+                        .filter(|x| same_document(x, item))
+                        .for_each(|x| x.checked = true)
+                });
+            // Keep the selection on the same document when it is still found
+            let selected = data
+                .selected
+                .and_then(|i| data.items_rich.get(i))
+                .and_then(|item| vec1.iter().position(|x| same_document(x, item)));
+            if let Some(i) = selected {
+                vec1[i].selected = true;
+            }
+            data.selected = selected;
             data.items_rich = Vector::from(vec1);
             Handled::Yes
         } else if let Some(pointer_state) = cmd.get(CHECK_CLICKED_FOR_POINTER) {
@@ -105,26 +222,23 @@ impl AppDelegate<AppState> for Delegate {
                     p.number = pointer_state.number;
                 }
             });
+            data.sync_columns();
             Handled::Yes
         } else if let Some(tail) = cmd.get(TAIL) {
             GLOBAL_STATE.lock().unwrap().tail = *tail;
             Handled::Yes
         } else if let Some(pointer_state) = cmd.get(CHECK_CLICKED_FOR_POINTER_SORT) {
+            match pointer_state.checked_sort {
+                true => data.sort_by(&pointer_state.text),
+                false => data.sort_by(""),
+            }
+            Handled::Yes
+        } else if let Some(pointer_state) = cmd.get(CHECK_CLICKED_FOR_POINTER_VOLATILE) {
             data.pointers.iter_mut().for_each(|p| {
                 if p.text == pointer_state.text {
-                    p.checked_sort = pointer_state.checked_sort;
-                } else {
-                    p.checked_sort = false;
+                    p.checked_volatile = pointer_state.checked_volatile;
                 }
             });
-            GLOBAL_STATE.lock().unwrap().sort = data
-                .pointers
-                .iter()
-                .filter(|p| p.checked_sort)
-                .map(|p| p.text.to_string())
-                .last()
-                .unwrap_or("".to_string());
-            data.tx.send(RESORT).unwrap();
             Handled::Yes
         } else if let Some(pointer_state) = cmd.get(CHECK_CLICKED_FOR_POINTER_VIEW) {
             data.pointers_view.iter_mut().for_each(|p| {
@@ -134,6 +248,57 @@ impl AppDelegate<AppState> for Delegate {
                 }
             });
             Handled::Yes
+        } else if let Some(key) = cmd.get(STOP_STREAM) {
+            data.tx
+                .send(CommandMessage::StopStream(key.to_string()))
+                .unwrap();
+            Handled::Yes
+        } else if let Some(key) = cmd.get(RESTART_STREAM) {
+            data.tx
+                .send(CommandMessage::RestartStream(key.to_string()))
+                .unwrap();
+            Handled::Yes
+        } else if let Some((template, exclude)) = cmd.get(PATTERN_FILTER) {
+            {
+                let mut state = GLOBAL_STATE.lock().unwrap();
+                state.pattern = template.to_string();
+                state.pattern_exclude = *exclude;
+            }
+            data.pattern_filter = match (template.is_empty(), exclude) {
+                (true, _) => "".to_string(),
+                (false, false) => format!("Pattern      {}", template),
+                (false, true) => format!("Excluding    {}", template),
+            };
+            ctx.submit_command(SEARCH.with((
+                (data.query.to_string(), data.not_query.to_string()),
+                data.exact,
+            )));
+            Handled::Yes
+        } else if let Some((level, checked)) = cmd.get(LEVEL_FILTER) {
+            {
+                let mut state = GLOBAL_STATE.lock().unwrap();
+                state.hidden_levels.retain(|l| l != level);
+                if !checked {
+                    state.hidden_levels.push(*level);
+                }
+            }
+            ctx.submit_command(SEARCH.with((
+                (data.query.to_string(), data.not_query.to_string()),
+                data.exact,
+            )));
+            Handled::Yes
+        } else if let Some((pointer, value)) = cmd.get(FOLLOW) {
+            data.follow_info = format!("Following    {} = {}", pointer, value);
+            data.follow.clear();
+            data.tx
+                .send(CommandMessage::Follow(
+                    pointer.to_string(),
+                    value.to_string(),
+                    data.timelimit as u64,
+                    data.viewlimit as usize,
+                ))
+                .unwrap();
+            Handled::Yes
         } else if let Some(_) = cmd.get(CLEAR_DB) {
             data.tx.send(CommandMessage::Clear).unwrap();
             Handled::Yes
@@ -145,6 +310,7 @@ impl AppDelegate<AppState> for Delegate {
                     q.0 .0.to_string(),
                     q.0 .1.to_string(),
                     q.1,
+                    data.collapse,
                     data.timelimit as u64,
                     data.viewlimit as usize,
                     pointers,
@@ -206,6 +372,238 @@ fn resolve_pointer(text: &str, ps: &str) -> String {
     ps.to_string()
 }
 
+#[derive(Clone, Copy)]
+enum Kind {
+    String,
+    Number,
+    Bool,
+    Null,
+}
+
+/// A row per value of the document, objects and arrays first with their
+/// fields under them. Lines that are not JSON are one row.
+fn tree(text: &str, words: &[&str]) -> Vector<TreeNode> {
+    let json = match serde_json::from_str::<Value>(text) {
+        Ok(json) => json,
+        Err(_) => {
+            return Vector::from(vec![node("", "", 0, text.to_string(), Kind::String, words)])
+        }
+    };
+    let mut nodes = vec![];
+    walk(&json, "", "", 0, words, &mut nodes);
+    Vector::from(nodes)
+}
+
+fn walk(
+    value: &Value,
+    pointer: &str,
+    key: &str,
+    depth: usize,
+    words: &[&str],
+    nodes: &mut Vec<TreeNode>,
+) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (k.to_string(), v)).collect(),
+        Value::Array(array) => array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        Value::String(s) => {
+            nodes.push(node(
+                pointer,
+                key,
+                depth,
+                s.to_string(),
+                Kind::String,
+                words,
+            ));
+            return;
+        }
+        Value::Number(n) => {
+            nodes.push(node(
+                pointer,
+                key,
+                depth,
+                n.to_string(),
+                Kind::Number,
+                words,
+            ));
+            return;
+        }
+        Value::Bool(b) => {
+            nodes.push(node(pointer, key, depth, b.to_string(), Kind::Bool, words));
+            return;
+        }
+        Value::Null => {
+            nodes.push(node(
+                pointer,
+                key,
+                depth,
+                "null".to_string(),
+                Kind::Null,
+                words,
+            ));
+            return;
+        }
+    };
+    // The root object is not a row of its own
+    let depth = match pointer.is_empty() {
+        true => depth,
+        false => {
+            let mut builder = RichTextBuilder::new();
+            push(
+                &mut builder,
+                &format!("{}{}", "  ".repeat(depth), key),
+                None,
+                words,
+            );
+            builder
+                .push(&match value {
+                    Value::Array(_) => format!("  [{}]", children.len()),
+                    _ => format!("  {{{}}}", children.len()),
+                })
+                .text_color(Color::rgb8(150, 150, 150));
+            nodes.push(TreeNode {
+                pointer: pointer.to_string(),
+                depth,
+                value: serde_json::to_string_pretty(value).unwrap_or_default(),
+                text: builder.build(),
+                container: true,
+                expanded: true,
+                hidden: false,
+            });
+            depth + 1
+        }
+    };
+    for (k, v) in children {
+        let escaped = k.replace('~', "~0").replace('/', "~1");
+        walk(
+            v,
+            &format!("{}/{}", pointer, escaped),
+            &k,
+            depth,
+            words,
+            nodes,
+        );
+    }
+}
+
+fn node(
+    pointer: &str,
+    key: &str,
+    depth: usize,
+    value: String,
+    kind: Kind,
+    words: &[&str],
+) -> TreeNode {
+    let color = match kind {
+        Kind::String => Color::rgb8(0x98, 0xC3, 0x79),
+        Kind::Number => Color::rgb8(0x61, 0xAF, 0xEF),
+        Kind::Bool => Color::rgb8(0xD1, 0x9A, 0x66),
+        Kind::Null => Color::rgb8(150, 150, 150),
+    };
+    let mut builder = RichTextBuilder::new();
+    let indent = "  ".repeat(depth);
+    match key.is_empty() {
+        true => push(&mut builder, &indent, None, words),
+        false => push(&mut builder, &format!("{}{}: ", indent, key), None, words),
+    }
+    push(&mut builder, &value, Some(color), words);
+    TreeNode {
+        pointer: pointer.to_string(),
+        depth,
+        value,
+        text: builder.build(),
+        container: false,
+        expanded: true,
+        hidden: false,
+    }
+}
+
+/// Adds the text in the color with the words of the query highlighted.
+fn push(builder: &mut RichTextBuilder, text: &str, color: Option<Color>, words: &[&str]) {
+    let words = words
+        .iter()
+        .filter(|w| !w.is_empty())
+        .copied()
+        .collect::<Vec<&str>>();
+    if words.is_empty() {
+        let mut attributes = builder.push(text);
+        if let Some(color) = color {
+            attributes.text_color(color);
+        }
+        return;
+    }
+    // Matched on the lowercase text, the original text is shown
+    let lower = text.to_lowercase();
+    let mut matched = vec![false; text.len()];
+    if lower.len() == text.len() {
+        for word in words {
+            for (start, m) in lower.match_indices(&word.to_lowercase()) {
+                matched[start..start + m.len()].fill(true);
+            }
+        }
+    }
+    let mut start = 0;
+    while start < text.len() {
+        let is_match = matched[start];
+        let mut end = start;
+        while end < text.len() && (matched[end] == is_match || !text.is_char_boundary(end)) {
+            end += 1;
+        }
+        let mut attributes = builder.push(&text[start..end]);
+        match (is_match, &color) {
+            (true, _) => {
+                attributes
+                    .weight(FontWeight::new(1000))
+                    .text_color(Color::rgb8(255, 180, 90));
+            }
+            (false, Some(color)) => {
+                attributes.text_color(color.clone());
+            }
+            (false, None) => {}
+        }
+        start = end;
+    }
+}
+
+/// The same document in results searched again, by its number when it has one.
+fn same_document(a: &ItemRich, b: &ItemRich) -> bool {
+    match b.number {
+        Some(_) => a.number == b.number,
+        None => a.text == b.text,
+    }
+}
+
+/// The leaves of a document with a value, ids first as they are what is
+/// usually followed.
+fn view_pointers(text: &str) -> Vector<ViewPointer> {
+    let json = match serde_json::from_str::<Value>(text) {
+        Ok(json @ Value::Object(_)) => json,
+        _ => return Vector::new(),
+    };
+    let mut pointers = generate_pointers(&json)
+        .into_iter()
+        .filter(|p| !p.ends_with('/'))
+        .filter_map(|p| {
+            let value = match json.pointer(&p)? {
+                Value::String(s) if !s.is_empty() => s.to_string(),
+                Value::Number(n) => n.to_string(),
+                _ => return None,
+            };
+            Some(ViewPointer { pointer: p, value })
+        })
+        .collect::<Vec<ViewPointer>>();
+    pointers.sort_by_key(|p| {
+        (
+            !p.pointer.to_lowercase().ends_with("id"),
+            p.pointer.to_string(),
+        )
+    });
+    Vector::from(pointers)
+}
+
 fn generate_pointers(json: &Value) -> Vec<String> {
     let mut pointers = vec![];
     let mut stack = VecDeque::new();
@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde_json::Value;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    JsonLines,
+    /// A column per pointer, the whole document when there are none.
+    Csv,
+}
+
+impl Format {
+    /// CSV for a `.csv` file, JSON Lines otherwise.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::JsonLines,
+        }
+    }
+}
+
+/// The value at the pointer as text, strings without their quotes and empty
+/// when the document has no such field.
+pub fn value(doc: &Value, pointer: &str) -> String {
+    match doc.pointer(pointer) {
+        None | Some(Value::Null) => "".to_string(),
+        Some(Value::String(s)) => s.to_string(),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn values(doc: &str, pointers: &[String]) -> Vec<String> {
+    if pointers.is_empty() {
+        return vec![doc.to_string()];
+    }
+    let json = serde_json::from_str::<Value>(doc).unwrap_or(Value::Null);
+    pointers.iter().map(|p| value(&json, p)).collect()
+}
+
+fn header(pointers: &[String]) -> Vec<String> {
+    match pointers.is_empty() {
+        true => vec!["document".to_string()],
+        false => pointers.to_vec(),
+    }
+}
+
+/// Tabs and line breaks in values become spaces.
+pub fn tsv(docs: &[String], pointers: &[String]) -> String {
+    std::iter::once(header(pointers))
+        .chain(docs.iter().map(|d| values(d, pointers)))
+        .map(|row| {
+            row.iter()
+                .map(|v| v.replace(['\t', '\r', '\n'], " "))
+                .collect::<Vec<String>>()
+                .join("\t")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Each document pretty printed, the ones that are not JSON as they are.
+pub fn pretty(docs: &[String]) -> String {
+    docs.iter()
+        .map(|d| match serde_json::from_str::<Value>(d) {
+            Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_else(|_| d.to_string()),
+            Err(_) => d.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+fn csv_row(row: &[String]) -> String {
+    row.iter()
+        .map(|v| csv_field(v))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Writes the documents to the file, returns how many were written.
+pub fn write(
+    path: &Path,
+    format: Format,
+    docs: impl Iterator<Item = impl AsRef<str>>,
+    pointers: &[String],
+) -> std::io::Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+    if format == Format::Csv {
+        writeln!(out, "{}", csv_row(&header(pointers)))?;
+    }
+    let mut count = 0;
+    for doc in docs {
+        let doc = doc.as_ref();
+        match format {
+            Format::JsonLines => writeln!(out, "{}", doc)?,
+            Format::Csv => writeln!(out, "{}", csv_row(&values(doc, pointers)))?,
+        }
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn quotes_csv_fields_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_row(&["a".to_string(), "b,c".to_string()]), "a,\"b,c\"");
+    }
+
+    #[test]
+    fn takes_values_at_pointers() {
+        let doc = json!({"a": "x", "b": 1, "c": null, "d": {"e": true}});
+        assert_eq!(value(&doc, "/a"), "x");
+        assert_eq!(value(&doc, "/b"), "1");
+        assert_eq!(value(&doc, "/c"), "");
+        assert_eq!(value(&doc, "/missing"), "");
+        assert_eq!(value(&doc, "/d"), r#"{"e":true}"#);
+    }
+
+    #[test]
+    fn writes_csv_and_json_lines() {
+        let dir = std::env::temp_dir().join(format!("melt-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let docs = [r#"{"a":"x,y","b":1}"#, "not json"];
+        let csv = dir.join("out.csv");
+        let pointers = ["/a".to_string(), "/b".to_string()];
+        assert_eq!(
+            write(&csv, Format::of(&csv), docs.iter(), &pointers).unwrap(),
+            2
+        );
+        assert_eq!(
+            std::fs::read_to_string(&csv).unwrap(),
+            "/a,/b\n\"x,y\",1\n,\n"
+        );
+        let jsonl = dir.join("out.jsonl");
+        assert_eq!(
+            write(&jsonl, Format::of(&jsonl), docs.iter(), &[]).unwrap(),
+            2
+        );
+        assert_eq!(
+            std::fs::read_to_string(&jsonl).unwrap(),
+            format!("{}\n{}\n", docs[0], docs[1])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
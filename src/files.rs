@@ -0,0 +1,403 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use flate2::read::MultiGzDecoder;
+use once_cell::sync::Lazy;
+use zstd::Decoder;
+
+use crate::index::CommandMessage;
+use crate::ingest::keep;
+use crate::sources::{record_line, set_state, StreamState};
+
+/// Files that are followed but currently not read from, keyed by status key.
+static PAUSED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Patterns and files followed, a pattern given twice or a file matching two
+/// patterns is read once.
+static PATTERNS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+static FOLLOWED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Compressed files imported, keyed by status key, to continue after a stop.
+static COMPRESSED: Lazy<Mutex<HashMap<String, Import>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static QUIT: AtomicBool = AtomicBool::new(false);
+
+/// Bytes read from a file at a time.
+const CHUNK: u64 = 1 << 20;
+
+/// A line without a line break longer than this is sent as it is.
+const MAX_LINE: usize = 1 << 20;
+
+#[derive(Clone)]
+struct Import {
+    path: PathBuf,
+    lines: u64,
+    running: bool,
+    done: bool,
+}
+
+struct Tail {
+    file: File,
+    id: u64,
+    offset: u64,
+    buff: Vec<u8>,
+}
+
+pub fn stop(key: &str) {
+    PAUSED.lock().unwrap().insert(key.to_string());
+    set_state(key, StreamState::Stopped);
+}
+
+/// Continues reading, a compressed file from the line it was stopped at.
+pub fn restart(key: &str, tx: &Sender<CommandMessage>) {
+    PAUSED.lock().unwrap().remove(key);
+    let import = COMPRESSED.lock().unwrap().get(key).cloned();
+    match import {
+        None => set_state(key, StreamState::Streaming),
+        Some(import) if import.done => set_state(key, StreamState::Ended),
+        Some(import) if import.running => set_state(key, StreamState::Streaming),
+        Some(import) => {
+            let tx = tx.clone();
+            thread::spawn(move || import_compressed(&import.path, &tx));
+        }
+    }
+}
+
+/// Ends every tail and import, on quit.
+pub fn stop_all() {
+    QUIT.store(true, Ordering::SeqCst);
+}
+
+fn key(path: &Path) -> String {
+    format!("file:{}", path.display())
+}
+
+/// Imports the files matching the pattern and follows appends to them like
+/// `tail -F`, reopening a file when it is rotated or truncated. A directory
+/// means every file in it. Compressed files (`.gz`, `.zst`) are imported once.
+pub fn tail_files(pattern: String, tx: Sender<CommandMessage>) {
+    let pattern = match Path::new(&pattern).is_dir() {
+        true => format!("{}/*", pattern.trim_end_matches(['/', '\\'])),
+        false => pattern,
+    };
+    if !PATTERNS.lock().unwrap().insert(pattern.to_string()) {
+        return;
+    }
+    thread::spawn(move || {
+        let mut tails: BTreeMap<PathBuf, Tail> = BTreeMap::new();
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        let mut ids: HashSet<u64> = HashSet::new();
+        let mut first = true;
+        while !QUIT.load(Ordering::SeqCst) {
+            for path in matching(&pattern) {
+                if seen.contains(&path) {
+                    continue;
+                }
+                seen.insert(path.to_path_buf());
+                if !FOLLOWED.lock().unwrap().insert(path.to_path_buf()) {
+                    continue;
+                }
+                let id = match file_id(&path) {
+                    Ok(id) => id,
+                    Err(_) => continue,
+                };
+                // Files showing up while following are most likely rotated copies of
+                // content that was already read, only new live files are imported.
+                if !first && (is_rotated(&path) || (id != 0 && ids.contains(&id))) {
+                    continue;
+                }
+                ids.insert(id);
+                if is_compressed(&path) {
+                    COMPRESSED.lock().unwrap().insert(
+                        key(&path),
+                        Import {
+                            path: path.to_path_buf(),
+                            lines: 0,
+                            running: false,
+                            done: false,
+                        },
+                    );
+                    import_compressed(&path, &tx);
+                    continue;
+                }
+                match File::open(&path) {
+                    Ok(file) => {
+                        set_state(&key(&path), StreamState::Streaming);
+                        tails.insert(
+                            path.to_path_buf(),
+                            Tail {
+                                file,
+                                id,
+                                offset: 0,
+                                buff: vec![],
+                            },
+                        );
+                    }
+                    Err(e) => set_state(&key(&path), StreamState::Error(e.to_string())),
+                }
+            }
+            first = false;
+
+            for (path, tail) in tails.iter_mut() {
+                let key = key(path);
+                if PAUSED.lock().unwrap().contains(&key) {
+                    continue;
+                }
+                if read_new(tail, &key, &tx).is_err() {
+                    return;
+                }
+                let id = match file_id(path) {
+                    Ok(id) => id,
+                    Err(_) => continue,
+                };
+                let len = match path.metadata() {
+                    Ok(m) => m.len(),
+                    Err(_) => continue,
+                };
+                if id != tail.id || len < tail.offset {
+                    match File::open(path) {
+                        Ok(file) => {
+                            ids.insert(id);
+                            *tail = Tail {
+                                file,
+                                id,
+                                offset: 0,
+                                buff: vec![],
+                            };
+                        }
+                        Err(e) => set_state(&key, StreamState::Error(e.to_string())),
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    });
+}
+
+fn matching(pattern: &str) -> Vec<PathBuf> {
+    let mut paths = match glob::glob(pattern) {
+        Ok(paths) => paths
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => vec![],
+    };
+    // Oldest first, so rotated files are imported before the live file.
+    paths.sort_by_key(|p| p.metadata().and_then(|m| m.modified()).ok());
+    paths
+}
+
+/// Reads what was appended since the last read, a chunk at a time, sending
+/// every complete line.
+fn read_new(tail: &mut Tail, key: &str, tx: &Sender<CommandMessage>) -> Result<(), ()> {
+    loop {
+        if PAUSED.lock().unwrap().contains(key) || QUIT.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut bytes = vec![];
+        let read = tail
+            .file
+            .seek(SeekFrom::Start(tail.offset))
+            .and_then(|_| (&mut tail.file).take(CHUNK).read_to_end(&mut bytes));
+        match read {
+            Ok(0) => return Ok(()),
+            Ok(n) => tail.offset += n as u64,
+            Err(e) => {
+                set_state(key, StreamState::Error(e.to_string()));
+                return Ok(());
+            }
+        }
+        tail.buff.extend(bytes);
+        let end = match tail.buff.iter().rposition(|b| *b == b'\n') {
+            Some(end) => end,
+            None if tail.buff.len() > MAX_LINE => tail.buff.len() - 1,
+            None => continue,
+        };
+        let lines = tail.buff.drain(..=end).collect::<Vec<u8>>();
+        for line in String::from_utf8_lossy(&lines).lines() {
+            send(line, key, tx)?;
+        }
+    }
+}
+
+/// Imports a compressed file once, from the line it was stopped at before.
+fn import_compressed(path: &Path, tx: &Sender<CommandMessage>) {
+    let key = key(path);
+    let skip = {
+        let mut imports = COMPRESSED.lock().unwrap();
+        match imports.get_mut(&key) {
+            Some(import) => {
+                import.running = true;
+                import.lines
+            }
+            None => 0,
+        }
+    };
+    let state = read_compressed(path, &key, skip, tx);
+    if let Some(import) = COMPRESSED.lock().unwrap().get_mut(&key) {
+        import.running = false;
+        import.done = matches!(state, StreamState::Ended);
+    }
+    set_state(&key, state);
+}
+
+fn read_compressed(path: &Path, key: &str, skip: u64, tx: &Sender<CommandMessage>) -> StreamState {
+    let reader: Box<dyn Read> = match File::open(path) {
+        Ok(file) => match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Box::new(MultiGzDecoder::new(file)),
+            _ => match Decoder::new(file) {
+                Ok(d) => Box::new(d),
+                Err(e) => return StreamState::Error(e.to_string()),
+            },
+        },
+        Err(e) => return StreamState::Error(e.to_string()),
+    };
+    set_state(key, StreamState::Streaming);
+    for line in BufReader::new(reader).split(b'\n').skip(skip as usize) {
+        if QUIT.load(Ordering::SeqCst) || PAUSED.lock().unwrap().contains(key) {
+            return StreamState::Stopped;
+        }
+        match line {
+            Ok(line) => {
+                if send(&String::from_utf8_lossy(&line), key, tx).is_err() {
+                    return StreamState::Stopped;
+                }
+                if let Some(import) = COMPRESSED.lock().unwrap().get_mut(key) {
+                    import.lines += 1;
+                }
+            }
+            Err(e) => return StreamState::Error(e.to_string()),
+        }
+    }
+    StreamState::Ended
+}
+
+fn send(line: &str, key: &str, tx: &Sender<CommandMessage>) -> Result<(), ()> {
+    let line = line.trim_end_matches('\r');
+    if line.trim().is_empty() {
+        return Ok(());
+    }
+    if !record_line(key, line.len()) || !keep(key, line) {
+        return Ok(());
+    }
+    tx.send(CommandMessage::InsertJson(
+        key.to_string(),
+        line.to_string(),
+        vec![],
+    ))
+    .map_err(|_| ())
+}
+
+fn is_compressed(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("gz") | Some("zst")
+    )
+}
+
+/// `app.log.1`, `app.log.2.gz` and the like.
+fn is_rotated(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let name = name.trim_end_matches(".gz").trim_end_matches(".zst");
+    match name.rsplit_once('.') {
+        None => false,
+        Some((_, suffix)) => !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+/// Identifies the file at the path apart from its name, so a renamed file is
+/// told from a new one with the same name.
+#[cfg(unix)]
+fn file_id(path: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(path.metadata()?.ino())
+}
+
+/// The file index, creation times are kept for a new file with the name of a
+/// file just renamed so they cannot tell a rotation.
+#[cfg(windows)]
+fn file_id(path: &Path) -> io::Result<u64> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+    let file = File::open(path)?;
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    match unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, &mut info) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crossbeam_channel::unbounded;
+
+    use super::*;
+
+    /// A file name of its own per test and run, tests run in parallel.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("melt-{}-{}", std::process::id(), name))
+    }
+
+    fn lines(rx: &crossbeam_channel::Receiver<CommandMessage>) -> Vec<String> {
+        rx.try_iter()
+            .filter_map(|m| match m {
+                CommandMessage::InsertJson(_, line, _) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_appends_from_the_offset() {
+        let path = temp_path("reads-appends.log");
+        let mut file = File::create(&path).unwrap();
+        write!(file, "one\ntwo\nthr").unwrap();
+        let (tx, rx) = unbounded();
+        let mut tail = Tail {
+            file: File::open(&path).unwrap(),
+            id: 0,
+            offset: 0,
+            buff: vec![],
+        };
+        read_new(&mut tail, "test:file", &tx).unwrap();
+        assert_eq!(lines(&rx), vec!["one", "two"]);
+        writeln!(file, "ee").unwrap();
+        read_new(&mut tail, "test:file", &tx).unwrap();
+        assert_eq!(lines(&rx), vec!["three"]);
+        assert_eq!(tail.offset, 14);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tells_a_rotated_file_from_the_new_one() {
+        let path = temp_path("rotates.log");
+        let rotated = temp_path("rotates.log.1");
+        File::create(&path).unwrap();
+        let id = file_id(&path).unwrap();
+        std::fs::rename(&path, &rotated).unwrap();
+        File::create(&path).unwrap();
+        assert_eq!(file_id(&rotated).unwrap(), id);
+        assert_ne!(file_id(&path).unwrap(), id);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+
+    #[test]
+    fn rotated_names() {
+        assert!(is_rotated(Path::new("app.log.1")));
+        assert!(is_rotated(Path::new("app.log.2.gz")));
+        assert!(!is_rotated(Path::new("app.log")));
+        assert!(!is_rotated(Path::new("app.gz")));
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::net::{TcpListener, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use serde_json::{Map, Value};
+
+use crate::index::{rfc3339, CommandMessage};
+use crate::ingest::keep;
+use crate::sources::{record_line, set_state, StreamState};
+use crate::syslog::{address, skip_past, ERROR_PAUSE, SEVERITIES};
+
+pub const GELF_PORT: u16 = 12201;
+/// Largest message taken, over TCP and after decompression.
+pub const MAX_MESSAGE: usize = 1024 * 1024;
+
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+const MAX_CHUNKS: usize = 128;
+/// Incomplete chunked messages are dropped after this, as in the GELF spec.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Incomplete chunked messages kept at once, the oldest is dropped for a new one.
+const MAX_PENDING: usize = 256;
+
+struct Chunks {
+    started: Instant,
+    parts: Vec<Option<Vec<u8>>>,
+}
+
+/// Listens for GELF over UDP, chunked or not and optionally zlib or gzip
+/// compressed, and over TCP with null-byte delimited messages.
+pub fn gelf_listener(tx_send: Sender<CommandMessage>) {
+    let udp_key = "gelf:udp";
+    match UdpSocket::bind(address(GELF_PORT)) {
+        Ok(socket) => {
+            let sender = tx_send.clone();
+            set_state(udp_key, StreamState::Connecting);
+            thread::spawn(move || {
+                let mut buf = vec![0; 65536];
+                let mut pending: HashMap<Vec<u8>, Chunks> = HashMap::new();
+                loop {
+                    let n = match socket.recv_from(&mut buf) {
+                        Ok((n, _)) => n,
+                        Err(e) => {
+                            set_state(udp_key, StreamState::Error(e.to_string()));
+                            thread::sleep(ERROR_PAUSE);
+                            continue;
+                        }
+                    };
+                    pending.retain(|_, c| c.started.elapsed() < CHUNK_TIMEOUT);
+                    let payload = match buf[..n].starts_with(&CHUNK_MAGIC) {
+                        true => match reassemble(&buf[..n], &mut pending) {
+                            None => continue,
+                            Some(p) => p,
+                        },
+                        false => buf[..n].to_vec(),
+                    };
+                    if send(&payload, udp_key, &sender).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Err(e) => set_state(udp_key, StreamState::Error(e.to_string())),
+    }
+
+    let tcp_key = "gelf:tcp";
+    let listener = match TcpListener::bind(address(GELF_PORT)) {
+        Ok(l) => l,
+        Err(e) => {
+            set_state(tcp_key, StreamState::Error(e.to_string()));
+            return;
+        }
+    };
+    set_state(tcp_key, StreamState::Connecting);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    set_state(tcp_key, StreamState::Error(e.to_string()));
+                    continue;
+                }
+            };
+            let sender = tx_send.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream);
+                while let Ok(Some(frame)) = read_frame(&mut reader) {
+                    if send(&frame, tcp_key, &sender).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Reads one null-byte delimited message, without the null byte. A message
+/// over `MAX_MESSAGE` is cut there and the rest skipped. `None` at end of
+/// stream.
+fn read_frame(reader: &mut impl BufRead) -> std::io::Result<Option<Vec<u8>>> {
+    let mut frame = vec![];
+    if reader
+        .by_ref()
+        .take(MAX_MESSAGE as u64)
+        .read_until(0, &mut frame)?
+        == 0
+    {
+        return Ok(None);
+    }
+    match frame.last() {
+        Some(0) => {
+            frame.pop();
+        }
+        _ if frame.len() == MAX_MESSAGE => skip_past(reader, 0)?,
+        _ => {}
+    }
+    Ok(Some(frame))
+}
+
+/// Stores a chunk and returns the whole message once every chunk has arrived.
+fn reassemble(datagram: &[u8], pending: &mut HashMap<Vec<u8>, Chunks>) -> Option<Vec<u8>> {
+    if datagram.len() < 12 {
+        return None;
+    }
+    let id = datagram[2..10].to_vec();
+    let sequence = datagram[10] as usize;
+    let count = datagram[11] as usize;
+    if count == 0 || count > MAX_CHUNKS || sequence >= count {
+        return None;
+    }
+    if !pending.contains_key(&id) && pending.len() >= MAX_PENDING {
+        let oldest = pending
+            .iter()
+            .min_by_key(|(_, c)| c.started)
+            .map(|(id, _)| id.to_vec());
+        if let Some(oldest) = oldest {
+            pending.remove(&oldest);
+        }
+    }
+    let chunks = pending.entry(id.to_vec()).or_insert_with(|| Chunks {
+        started: Instant::now(),
+        parts: vec![None; count],
+    });
+    if chunks.parts.len() != count {
+        return None;
+    }
+    chunks.parts[sequence] = Some(datagram[12..].to_vec());
+    if chunks.parts.iter().any(|p| p.is_none()) {
+        return None;
+    }
+    pending
+        .remove(&id)
+        .map(|c| c.parts.into_iter().flatten().flatten().collect())
+}
+
+fn send(payload: &[u8], key: &str, sender: &Sender<CommandMessage>) -> Result<(), ()> {
+    let message = match decompress(payload) {
+        Ok(m) => m,
+        Err(e) => {
+            set_state(key, StreamState::Error(e.to_string()));
+            return Ok(());
+        }
+    };
+    if message.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(());
+    }
+    if !record_line(key, message.len()) {
+        return Ok(());
+    }
+    let doc = parse(&message);
+    if !keep(key, &doc) {
+        return Ok(());
+    }
+    sender
+        .send(CommandMessage::InsertJson(key.to_string(), doc, vec![]))
+        .map_err(|_| ())
+}
+
+/// Decompresses zlib and gzip messages, one decompressing to more than
+/// `MAX_MESSAGE` is an error.
+fn decompress(payload: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = vec![];
+    let limit = MAX_MESSAGE as u64 + 1;
+    match payload {
+        [0x1f, 0x8b, ..] => {
+            MultiGzDecoder::new(payload)
+                .take(limit)
+                .read_to_end(&mut decompressed)?;
+        }
+        [0x78, ..] => {
+            ZlibDecoder::new(payload)
+                .take(limit)
+                .read_to_end(&mut decompressed)?;
+        }
+        _ => return Ok(payload.to_vec()),
+    };
+    if decompressed.len() > MAX_MESSAGE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("message decompresses to over {} bytes", MAX_MESSAGE),
+        ));
+    }
+    Ok(decompressed)
+}
+
+/// Converts a GELF message into a document. `short_message` becomes `message`,
+/// the epoch `timestamp` RFC 3339 and additional `_field`s top-level fields.
+fn parse(message: &[u8]) -> String {
+    let gelf = match serde_json::from_slice::<Value>(message) {
+        Ok(Value::Object(map)) => map,
+        _ => return String::from_utf8_lossy(message).to_string(),
+    };
+    let mut map = Map::new();
+    for (key, value) in gelf {
+        match key.as_str() {
+            "short_message" => {
+                map.insert("message".to_string(), value);
+            }
+            "timestamp" => match value.as_f64() {
+                Some(t) => {
+                    map.insert(
+                        "timestamp".to_string(),
+                        Value::from(rfc3339((t * 1_000_000.0).round() as i128 * 1000)),
+                    );
+                }
+                None => {
+                    map.insert(key, value);
+                }
+            },
+            "level" => {
+                if let Some(severity) = value.as_u64().and_then(|l| SEVERITIES.get(l as usize)) {
+                    map.insert("severity".to_string(), Value::from(*severity));
+                }
+                map.insert(key, value);
+            }
+            // _id is reserved by the spec
+            "_id" => {}
+            _ => match key.strip_prefix('_') {
+                Some(field) => {
+                    map.insert(field.to_string(), value);
+                }
+                None => {
+                    map.insert(key, value);
+                }
+            },
+        }
+    }
+    Value::Object(map).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn chunk(id: u8, sequence: u8, count: u8, data: &[u8]) -> Vec<u8> {
+        let mut chunk = CHUNK_MAGIC.to_vec();
+        chunk.extend([id; 8]);
+        chunk.extend([sequence, count]);
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn reassembles_chunks_in_any_order() {
+        let mut pending = HashMap::new();
+        assert_eq!(reassemble(&chunk(1, 1, 3, b"b"), &mut pending), None);
+        assert_eq!(reassemble(&chunk(2, 0, 2, b"x"), &mut pending), None);
+        assert_eq!(reassemble(&chunk(1, 2, 3, b"c"), &mut pending), None);
+        assert_eq!(
+            reassemble(&chunk(1, 0, 3, b"a"), &mut pending),
+            Some(b"abc".to_vec())
+        );
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn ignores_bad_chunks() {
+        let mut pending = HashMap::new();
+        assert_eq!(reassemble(&chunk(1, 2, 2, b"a"), &mut pending), None);
+        assert_eq!(reassemble(&chunk(1, 0, 0, b"a"), &mut pending), None);
+        assert_eq!(reassemble(&CHUNK_MAGIC, &mut pending), None);
+        assert!(pending.is_empty());
+        assert_eq!(reassemble(&chunk(1, 0, 2, b"a"), &mut pending), None);
+        assert_eq!(reassemble(&chunk(1, 1, 3, b"b"), &mut pending), None);
+        assert_eq!(
+            reassemble(&chunk(1, 1, 2, b"b"), &mut pending),
+            Some(b"ab".to_vec())
+        );
+    }
+
+    #[test]
+    fn drops_the_oldest_pending_message() {
+        let mut pending = HashMap::new();
+        assert_eq!(reassemble(&chunk(0, 0, 2, b"a"), &mut pending), None);
+        thread::sleep(Duration::from_millis(2));
+        for i in 1..=MAX_PENDING as u16 {
+            let mut chunk = chunk(0, 0, 2, b"x");
+            chunk[2..4].copy_from_slice(&i.to_be_bytes());
+            assert_eq!(reassemble(&chunk, &mut pending), None);
+        }
+        assert_eq!(pending.len(), MAX_PENDING);
+        assert_eq!(reassemble(&chunk(0, 1, 2, b"b"), &mut pending), None);
+    }
+
+    #[test]
+    fn caps_decompressed_messages() {
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(b"{}").unwrap();
+        assert_eq!(decompress(&gzip.finish().unwrap()).unwrap(), b"{}");
+
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(&vec![b' '; MAX_MESSAGE + 1]).unwrap();
+        assert!(decompress(&gzip.finish().unwrap()).is_err());
+    }
+
+    #[test]
+    fn cuts_long_tcp_messages() {
+        let mut stream = b"{}\0".to_vec();
+        stream.extend(vec![b'a'; MAX_MESSAGE * 2]);
+        stream.extend(b"\0{\"a\":1}\0");
+        let mut reader = BufReader::with_capacity(1024, stream.as_slice());
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"{}");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap().len(), MAX_MESSAGE);
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"{\"a\":1}");
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_messages() {
+        let doc = parse(
+            br#"{"short_message":"hi","timestamp":1700000000.5,"level":3,"_user":"a","_id":"x"}"#,
+        );
+        let doc = serde_json::from_str::<Value>(&doc).unwrap();
+        assert_eq!(doc["message"], "hi");
+        assert_eq!(doc["severity"], "err");
+        assert_eq!(doc["user"], "a");
+        assert!(doc.get("id").is_none());
+        assert!(doc["timestamp"]
+            .as_str()
+            .unwrap()
+            .starts_with("2023-11-14T22:13:20.5"));
+    }
+}
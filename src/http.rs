@@ -0,0 +1,426 @@
+use std::convert::Infallible;
+use std::io::Read;
+use std::net::SocketAddr;
+
+use crossbeam_channel::Sender;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prost::Message;
+use serde_json::{json, Value};
+
+use crate::index::{rfc3339, tag, CommandMessage};
+use crate::ingest::keep;
+use crate::otlp;
+use crate::sources::{record_line, set_state, StreamState};
+
+pub const HTTP_ADDRESS: &str = "127.0.0.1:7998";
+/// The default OTLP/HTTP port, so exporters work without configuring an endpoint.
+pub const OTLP_ADDRESS: &str = "127.0.0.1:4318";
+
+/// Largest request body taken, before and after decompression.
+pub const MAX_BODY: usize = 32 * 1024 * 1024;
+
+/// Serves the HTTP ingest endpoints:
+/// `POST /ingest` JSON Lines,
+/// `POST /_bulk` and `POST /<index>/_bulk` Elasticsearch bulk API,
+/// `POST /loki/api/v1/push` Loki push API, JSON or snappy compressed protobuf,
+/// `POST /v1/logs` OpenTelemetry OTLP/HTTP logs, JSON or protobuf.
+/// Bodies may be `gzip`, `deflate` or `zstd` content-encoded. Responses are sent
+/// once every document is handed to the index. Failing to listen is shown
+/// under the status key.
+pub async fn http_listener(
+    key: &'static str,
+    address: &'static str,
+    tx_send: Sender<CommandMessage>,
+) {
+    let address: SocketAddr = address.parse().unwrap();
+    let make_service = make_service_fn(move |_| {
+        let tx = tx_send.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(request, tx.clone()))) }
+    });
+    let server = match Server::try_bind(&address) {
+        Ok(s) => s,
+        Err(e) => {
+            set_state(key, StreamState::Error(e.to_string()));
+            return;
+        }
+    };
+    if let Err(e) = server.serve(make_service).await {
+        set_state(key, StreamState::Error(e.to_string()));
+    }
+}
+
+async fn handle(
+    request: Request<Body>,
+    tx: Sender<CommandMessage>,
+) -> Result<Response<Body>, Infallible> {
+    let path = request.uri().path().to_string();
+    let method = request.method().clone();
+    if method == Method::GET || method == Method::HEAD {
+        // Shippers speaking the Elasticsearch API check the server first.
+        return Ok(match path.as_str() {
+            "/" => json_response(
+                StatusCode::OK,
+                json!({"name": "melt", "version": {"number": "8.0.0"}, "tagline": "You Know, for Search"}),
+            ),
+            _ => empty_response(StatusCode::NOT_FOUND),
+        });
+    }
+    if method != Method::POST && method != Method::PUT {
+        return Ok(empty_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let encoding = header(&request, CONTENT_ENCODING);
+    let content_type = header(&request, CONTENT_TYPE);
+    let body = match read_body(request.into_body()).await {
+        Ok(b) => b,
+        Err(response) => return Ok(response),
+    };
+    let body = match decode(&encoding, body) {
+        Ok(b) => b,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e)),
+    };
+
+    let response = if path == "/ingest" {
+        let docs = String::from_utf8_lossy(&body)
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        insert("http:ingest", docs, tx).await;
+        empty_response(StatusCode::NO_CONTENT)
+    } else if path == "/_bulk" || path.ends_with("/_bulk") {
+        let (docs, items) = bulk(&String::from_utf8_lossy(&body));
+        insert("http:bulk", docs, tx).await;
+        json_response(
+            StatusCode::OK,
+            json!({"took": 0, "errors": bulk_errors(&items), "items": items}),
+        )
+    } else if path == "/loki/api/v1/push" {
+        let docs = match content_type.starts_with("application/json") {
+            true => loki_json(&body),
+            false => loki_protobuf(&body),
+        };
+        match docs {
+            Ok(docs) => {
+                insert("http:loki", docs, tx).await;
+                empty_response(StatusCode::NO_CONTENT)
+            }
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e),
+        }
+    } else if path == "/v1/logs" {
+        let json = content_type.starts_with("application/json");
+        let docs = match json {
+            true => otlp::logs_json(&body),
+            false => otlp::logs_protobuf(&body),
+        };
+        match docs {
+            Ok(docs) => {
+                insert("http:otlp", docs, tx).await;
+                match json {
+                    true => json_response(StatusCode::OK, json!({})),
+                    false => Response::builder()
+                        .status(StatusCode::OK)
+                        .header(CONTENT_TYPE, "application/x-protobuf")
+                        .body(Body::empty())
+                        .unwrap(),
+                }
+            }
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e),
+        }
+    } else {
+        empty_response(StatusCode::NOT_FOUND)
+    };
+    Ok(response)
+}
+
+fn header(request: &Request<Body>, name: hyper::header::HeaderName) -> String {
+    request
+        .headers()
+        .get(name)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// Reads the body, refusing it once it grows over `MAX_BODY`.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, Response<Body>> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))?;
+        if bytes.len() + chunk.len() > MAX_BODY {
+            return Err(error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("body over {} bytes", MAX_BODY),
+            ));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Decompresses the body, a body decompressing to more than `MAX_BODY` is an
+/// error.
+fn decode(encoding: &str, body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut decoded = vec![];
+    let limit = MAX_BODY as u64 + 1;
+    let result = match encoding {
+        "" | "identity" => return Ok(body),
+        "gzip" => MultiGzDecoder::new(body.as_slice())
+            .take(limit)
+            .read_to_end(&mut decoded),
+        "deflate" => ZlibDecoder::new(body.as_slice())
+            .take(limit)
+            .read_to_end(&mut decoded),
+        "zstd" => zstd::stream::read::Decoder::new(body.as_slice())
+            .and_then(|d| d.take(limit).read_to_end(&mut decoded)),
+        e => return Err(format!("unsupported content encoding {}", e)),
+    };
+    result.map_err(|e| e.to_string())?;
+    match decoded.len() > MAX_BODY {
+        true => Err(format!("body decompresses to over {} bytes", MAX_BODY)),
+        false => Ok(decoded),
+    }
+}
+
+/// Hands the documents to the index, off the async workers as the channel blocks.
+async fn insert(key: &'static str, docs: Vec<String>, tx: Sender<CommandMessage>) {
+    let _ = tokio::task::spawn_blocking(move || {
+        for doc in docs {
+            if !record_line(key, doc.len()) || !keep(key, &doc) {
+                continue;
+            }
+            if tx
+                .send(CommandMessage::InsertJson(key.to_string(), doc, vec![]))
+                .is_err()
+            {
+                return;
+            }
+        }
+    })
+    .await;
+}
+
+/// Splits a bulk body into the documents to index and the response items.
+/// Only `index` and `create` carry a document, `update` and `delete` are
+/// acknowledged and ignored.
+fn bulk(body: &str) -> (Vec<String>, Vec<Value>) {
+    let mut docs = vec![];
+    let mut items = vec![];
+    let mut lines = body.lines().filter(|l| !l.trim().is_empty());
+    while let Some(line) = lines.next() {
+        let action = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(map)) => map,
+            _ => continue,
+        };
+        let (name, meta) = match action.iter().next() {
+            None => continue,
+            Some((name, meta)) => (name.to_string(), meta.clone()),
+        };
+        let index = meta.get("_index").cloned().unwrap_or(Value::from("melt"));
+        let (status, result) = match name.as_str() {
+            "index" | "create" => match lines.next() {
+                Some(doc) => {
+                    docs.push(match index.as_str() {
+                        Some(i) => tag("http:bulk", doc, &[("_index", i)]),
+                        None => doc.to_string(),
+                    });
+                    (201, "created")
+                }
+                None => (400, "noop"),
+            },
+            "update" => {
+                lines.next();
+                (200, "noop")
+            }
+            _ => (200, "noop"),
+        };
+        items.push(json!({ name: {"_index": index, "status": status, "result": result} }));
+    }
+    (docs, items)
+}
+
+/// Whether any item failed, shippers retry the request when it did.
+fn bulk_errors(items: &[Value]) -> bool {
+    items
+        .iter()
+        .filter_map(|i| i.as_object()?.values().next()?.get("status")?.as_u64())
+        .any(|status| status >= 400)
+}
+
+fn loki_json(body: &[u8]) -> Result<Vec<String>, String> {
+    let push = serde_json::from_slice::<Value>(body).map_err(|e| e.to_string())?;
+    let mut docs = vec![];
+    for stream in push["streams"].as_array().unwrap_or(&vec![]) {
+        let labels = match &stream["stream"] {
+            Value::Object(map) => map
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or("").to_string()))
+                .collect::<Vec<(String, String)>>(),
+            _ => vec![],
+        };
+        for value in stream["values"].as_array().unwrap_or(&vec![]) {
+            let nanos = value[0].as_str().and_then(|t| t.parse::<i128>().ok());
+            let line = value[1].as_str().unwrap_or("");
+            docs.push(loki_doc(&labels, nanos, line));
+        }
+    }
+    Ok(docs)
+}
+
+fn loki_protobuf(body: &[u8]) -> Result<Vec<String>, String> {
+    if snap::raw::decompress_len(body).map_err(|e| e.to_string())? > MAX_BODY {
+        return Err(format!("body decompresses to over {} bytes", MAX_BODY));
+    }
+    let decompressed = snap::raw::Decoder::new()
+        .decompress_vec(body)
+        .map_err(|e| e.to_string())?;
+    let push = PushRequest::decode(decompressed.as_slice()).map_err(|e| e.to_string())?;
+    let mut docs = vec![];
+    for stream in push.streams {
+        let labels = parse_labels(&stream.labels);
+        for entry in stream.entries {
+            let nanos = entry
+                .timestamp
+                .map(|t| t.seconds as i128 * 1_000_000_000 + t.nanos as i128);
+            docs.push(loki_doc(&labels, nanos, &entry.line));
+        }
+    }
+    Ok(docs)
+}
+
+fn loki_doc(labels: &[(String, String)], nanos: Option<i128>, line: &str) -> String {
+    let mut tags = labels
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<(&str, &str)>>();
+    let timestamp = nanos.map(rfc3339);
+    if let Some(t) = &timestamp {
+        tags.push(("timestamp", t.as_str()));
+    }
+    tag("http:loki", line, &tags)
+}
+
+/// Parses a Prometheus style label set, `{app="api", pod="api-1"}`.
+fn parse_labels(labels: &str) -> Vec<(String, String)> {
+    let mut result = vec![];
+    let mut rest = labels.trim().trim_start_matches('{');
+    while let Some((name, value)) = rest.split_once("=\"") {
+        let name = name.trim_start_matches([',', ' ']).trim();
+        let mut unescaped = String::new();
+        let mut chars = value.char_indices();
+        let mut end = value.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => unescaped.push('\n'),
+                    Some((_, e)) => unescaped.push(e),
+                    None => {}
+                },
+                '"' => {
+                    end = i + 1;
+                    break;
+                }
+                c => unescaped.push(c),
+            }
+        }
+        result.push((name.to_string(), unescaped));
+        rest = &value[end..];
+    }
+    result
+}
+
+fn json_response(status: StatusCode, value: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response<Body> {
+    json_response(status, json!({ "error": error }))
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct PushRequest {
+    #[prost(message, repeated, tag = "1")]
+    streams: Vec<StreamAdapter>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StreamAdapter {
+    #[prost(string, tag = "1")]
+    labels: String,
+    #[prost(message, repeated, tag = "2")]
+    entries: Vec<EntryAdapter>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EntryAdapter {
+    #[prost(message, optional, tag = "1")]
+    timestamp: Option<Timestamp>,
+    #[prost(string, tag = "2")]
+    line: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Timestamp {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn bulk_reports_failed_items() {
+        let (docs, items) = bulk("{\"index\":{}}\n{\"a\":1}\n{\"delete\":{}}\n");
+        assert_eq!(docs.len(), 1);
+        assert!(!bulk_errors(&items));
+
+        let (docs, items) = bulk("{\"index\":{}}\n{\"a\":1}\n{\"create\":{}}\n");
+        assert_eq!(docs.len(), 1);
+        assert!(bulk_errors(&items));
+    }
+
+    #[test]
+    fn decode_caps_the_decompressed_size() {
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(b"a line\n").unwrap();
+        let small = gzip.finish().unwrap();
+        assert_eq!(decode("gzip", small).unwrap(), b"a line\n");
+
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(&vec![b'a'; MAX_BODY + 1]).unwrap();
+        let bomb = gzip.finish().unwrap();
+        assert!(decode("gzip", bomb).is_err());
+        assert!(decode("br", vec![]).is_err());
+    }
+
+    #[test]
+    fn reads_loki_json() {
+        let body =
+            br#"{"streams":[{"stream":{"app":"web"},"values":[["1700000000000000000","hello"]]}]}"#;
+        let docs = loki_json(body).unwrap();
+        assert_eq!(docs.len(), 1);
+        assert!(docs[0].contains("hello"));
+        assert!(docs[0].contains("web"));
+    }
+}
@@ -1,36 +1,47 @@
-use std::collections::BTreeMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Error, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, BufReader, Error, IsTerminal, Read, Seek, SeekFrom, Write};
 use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use std::{fs, io, thread};
 
 use bincode::deserialize;
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
 use druid::im::Vector;
 use druid::{ExtEventSink, Target};
 use fnv::FnvHashSet;
 use human_bytes::human_bytes;
 use jsonptr::{Pointer, ResolveMut};
-use k8s_openapi::api::core::v1::Pod;
-use kube::api::{ListParams, LogParams};
-use kube::{Api, Client};
 use melt_rs::get_search_index;
 use melt_rs::index::SearchIndex;
 use memchr::memmem::Finder;
 use num_format::{Locale, ToFormattedString};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{Map, Value};
 use tokio::task::JoinHandle;
-use tokio::time::sleep;
-use tokio_stream::StreamExt;
 use uuid::Uuid;
 use zstd::dict::{DecoderDictionary, EncoderDictionary};
 use zstd::{Decoder, Encoder};
 
-use crate::data::{AppState, Item, PointerState};
+use crate::data::{
+    AnomalyStatus, AppState, FollowGroup, Item, PatternStatus, PointerState, PointerStateItem,
+};
 use crate::delegate::{SEARCH, SEARCH_RESULT};
+use crate::export;
+use crate::files;
+use crate::gelf::gelf_listener;
+use crate::http::{http_listener, HTTP_ADDRESS, OTLP_ADDRESS};
+use crate::ingest::{keep, parse, pipeline, structure, Multiline, Tags};
+use crate::level::level;
+use crate::order::{Location, Order};
+use crate::patterns::{message, Change, Template, Timeline};
+use crate::pods::PodStreams;
+use crate::sources;
+use crate::sources::{record_line, set_state, StreamState};
+use crate::syslog::syslog_listener;
 use crate::GLOBAL_STATE;
 
 pub static GLOBAL_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -43,6 +54,7 @@ struct MemStore {
     dict_dec: DecoderDictionary<'static>,
     data_fd: File,
     ser: MemStoreSer,
+    order: Order,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -61,6 +73,137 @@ struct Entry {
     len: usize,
 }
 
+const ORDER_PATH: &str = ".melt_order.dat";
+
+/// Templates shown in the patterns panel.
+const PATTERN_LIMIT: usize = 200;
+
+/// Fields naming the service, pod or host a document came from, for grouping
+/// a followed id.
+const SERVICE_POINTERS: [&str; 8] = [
+    "/service",
+    "/service.name",
+    "/app",
+    "/app_name",
+    "/kubernetes/labels/app",
+    "/pod",
+    "/container",
+    "/host",
+];
+
+/// Fields holding the time of a document when no sort pointer is set.
+const TIME_POINTERS: [&str; 4] = ["/timestamp", "/@timestamp", "/time", "/ts"];
+
+fn time_pointers() -> Vec<String> {
+    let sort = GLOBAL_STATE.lock().unwrap().sort.to_string();
+    match sort.is_empty() {
+        true => TIME_POINTERS.iter().map(|p| p.to_string()).collect(),
+        false => vec![sort],
+    }
+}
+
+/// The time of a document in nanoseconds, from the first time pointer it has.
+fn time_of(doc: &str, pointers: &[String]) -> Option<i128> {
+    let value = serde_json::from_str::<Value>(doc).ok()?;
+    pointers.iter().find_map(|p| match value.pointer(p)? {
+        Value::String(s) => parse_time(s),
+        Value::Number(n) => parse_time(&n.to_string()),
+        _ => None,
+    })
+}
+
+/// A search result, the newest of `count` collapsed documents.
+struct Found {
+    text: String,
+    /// The number of the document in the ingest order of its source.
+    number: Option<u64>,
+    count: usize,
+    first_seen: String,
+    last_seen: String,
+}
+
+/// Collects search results up to the limit, grouping documents by their text
+/// without the volatile pointers when collapsing.
+struct Collapse<'a> {
+    limit: usize,
+    volatile: Option<&'a [String]>,
+    found: Vec<Found>,
+    groups: HashMap<String, usize>,
+    time_pointers: Vec<String>,
+}
+
+impl<'a> Collapse<'a> {
+    fn new(limit: usize, volatile: Option<&'a [String]>) -> Self {
+        Collapse {
+            limit,
+            volatile,
+            found: vec![],
+            groups: HashMap::new(),
+            time_pointers: time_pointers(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.found.len()
+    }
+
+    /// Adds a document, older than the ones before it, and returns whether there
+    /// is room for more.
+    fn push(&mut self, text: String, number: Option<u64>) -> bool {
+        let volatile = match self.volatile {
+            None => {
+                self.found.push(Found {
+                    text,
+                    number,
+                    count: 1,
+                    first_seen: "".to_string(),
+                    last_seen: "".to_string(),
+                });
+                return self.found.len() < self.limit;
+            }
+            Some(v) => v,
+        };
+        let key = match serde_json::from_str::<Value>(&text) {
+            Ok(mut json) => {
+                for pointer in volatile {
+                    if let Some(v) = json.pointer_mut(pointer) {
+                        *v = Value::Null;
+                    }
+                }
+                json.to_string()
+            }
+            Err(_) => text.to_string(),
+        };
+        let seen = self
+            .time_pointers
+            .iter()
+            .find_map(|p| resolve_pointer_some(&text, p))
+            .unwrap_or_default();
+        match self.groups.get(&key) {
+            Some(i) => {
+                let found = &mut self.found[*i];
+                found.count += 1;
+                found.first_seen = seen;
+            }
+            None => {
+                self.groups.insert(key, self.found.len());
+                self.found.push(Found {
+                    text,
+                    number,
+                    count: 1,
+                    first_seen: seen.to_string(),
+                    last_seen: seen,
+                });
+            }
+        }
+        self.found.len() < self.limit
+    }
+
+    fn found(self) -> Vec<Found> {
+        self.found
+    }
+}
+
 impl MemStore {
     fn open() -> io::Result<Self> {
         let data_fd = OpenOptions::new()
@@ -74,6 +217,7 @@ impl MemStore {
             dict_dec: DecoderDictionary::copy(ser.dict.clone().as_slice()),
             data_fd,
             ser,
+            order: MemStore::load_order(),
         };
 
         Ok(store)
@@ -90,6 +234,17 @@ impl MemStore {
             )
             .collect::<Vec<(String, String)>>();
 
+        self.order.moved(
+            map.keys()
+                .zip(vec.iter())
+                .map(|(from, to)| {
+                    (
+                        Location::Memory(from.as_str().into()),
+                        Location::Memory(to.0.as_str().into()),
+                    )
+                })
+                .collect(),
+        );
         self.ser.lines.clear();
         vec.iter().for_each(|m| {
             self.ser.lines.insert(m.0.clone(), m.1.clone());
@@ -130,6 +285,7 @@ impl MemStore {
         self.ser.index_fd.clear();
         self.ser.bytes = 0;
         self.ser.bytes_internal = 0;
+        self.order.clear();
     }
 
     fn compress_with_dict(&self, input: &str) -> io::Result<Vec<u8>> {
@@ -170,9 +326,13 @@ impl MemStore {
                 self.dict_dec = DecoderDictionary::copy(&self.ser.dict);
             }
 
-            let val = self.ser.lines.pop_last().unwrap().1;
+            let (memory_key, val) = self.ser.lines.pop_last().unwrap();
 
             let key = self.ser.index.add(&val);
+            self.order.moved(vec![(
+                Location::Memory(memory_key.into()),
+                Location::Disk(key),
+            )]);
             self.ser.bytes_internal -= val.len();
             let compressed = self.compress_with_dict(&val).unwrap();
             self.put(key, &compressed).unwrap();
@@ -183,6 +343,59 @@ impl MemStore {
             .insert(sort_column.to_string(), value.to_string());
     }
 
+    fn insert(&mut self, source: &str, json: &str) {
+        let key = match resolve_pointer_some(json, &GLOBAL_STATE.lock().unwrap().sort) {
+            None => Uuid::new_v4().to_string(),
+            Some(p) => p,
+        };
+        self.add(&key, json);
+        self.order.add(source, Location::Memory(key.into()));
+    }
+
+    fn document(&self, location: &Location) -> Option<String> {
+        match location {
+            Location::Memory(key) => self.ser.lines.get(&**key).cloned(),
+            Location::Disk(key) => self.get(key).ok(),
+        }
+    }
+
+    /// The documents of the same source around a document, in ingest order,
+    /// with the position of the document among them.
+    fn context(
+        &self,
+        number: u64,
+        n: usize,
+    ) -> Option<(String, Vec<(Option<u64>, String)>, usize)> {
+        let (source, locations, position) = self.order.context(number, n)?;
+        let documents = locations
+            .iter()
+            .map(|l| (self.order.number(l), self.document(l).unwrap_or_default()))
+            .collect();
+        Some((source, documents, position))
+    }
+
+    /// Parses an assembled event from the source into documents and adds them,
+    /// returning how many were added.
+    fn index(&mut self, source: &str, event: &str, tags: &Tags) -> usize {
+        let doc = match tags.is_empty() {
+            true => structure(source, event),
+            false => {
+                let tags = tags
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect::<Vec<(&str, &str)>>();
+                tag(source, event, &tags)
+            }
+        };
+        let docs = pipeline(source, doc);
+        docs.iter().for_each(|doc| self.insert(source, doc));
+        docs.len()
+    }
+
+    /// Newest first, with `collapse` documents that are equal apart from the
+    /// given volatile pointers become one result counting them. When levels are
+    /// hidden also counts the matches looked at per level, by `Level::index`,
+    /// otherwise the results are all the matches looked at.
     fn find(
         &mut self,
         query: &str,
@@ -190,56 +403,228 @@ impl MemStore {
         exact: bool,
         limit: usize,
         time: u128,
-    ) -> Vec<String> {
+        collapse: Option<&[String]>,
+    ) -> (Vec<Found>, Option<[usize; 4]>) {
         let query = query.to_lowercase();
         let finder_query = Self::get_finder(exact, &query);
         let query_neq = query_neq.to_lowercase();
         let finder_query_neq = Self::get_finder(exact, &query_neq);
-        let mut result = self
-            .ser
+        let (pattern, pattern_exclude) = {
+            let state = GLOBAL_STATE.lock().unwrap();
+            (Template::new(&state.pattern), state.pattern_exclude)
+        };
+        let is_pattern = |s: &str| pattern.is_empty() || pattern.matches(s) != pattern_exclude;
+        let (level_pointer, hidden_levels) = {
+            let state = GLOBAL_STATE.lock().unwrap();
+            (state.level_pointer.to_string(), state.hidden_levels.clone())
+        };
+        // Levels of the matches looked at, hidden ones too, documents without a
+        // level are always shown. Without hidden levels every match looked at
+        // is a result and the caller counts those.
+        let filter_levels = !hidden_levels.is_empty();
+        let levels = Cell::new([0; 4]);
+        let is_level = |s: &str| {
+            !filter_levels
+                || match level(s, &level_pointer) {
+                    None => true,
+                    Some(l) => {
+                        let mut counts = levels.get();
+                        counts[l.index()] += 1;
+                        levels.set(counts);
+                        !hidden_levels.contains(&l)
+                    }
+                }
+        };
+        let mut result = Collapse::new(limit, collapse);
+        self.ser
             .lines
-            .values()
+            .iter()
             .rev()
-            .into_iter()
-            .filter(|s| {
+            .filter(|(_, s)| {
                 (query_neq.is_empty() || !self.is_match(&finder_query_neq, s))
                     && self.is_match(&finder_query, s)
+                    && is_pattern(s)
+                    && is_level(s)
             })
-            .take(limit)
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
+            .take_while(|(k, s)| result.push(s.to_string(), self.memory_number(k)))
+            .for_each(drop);
         if result.len() < limit {
-            let mut positive_keys = self.ser.index.search(&query, exact);
-            let mut negative_keys = self.ser.index.search_or(&query_neq);
-            if !query_neq.is_empty() {
-                let set: FnvHashSet<usize> = positive_keys.iter().cloned().collect();
-                let set_neg: FnvHashSet<usize> = negative_keys.iter().cloned().collect();
-                negative_keys.retain(|x| set.contains(x));
-                positive_keys.retain(|x| !set_neg.contains(x));
-            }
+            let (positive_keys, negative_keys) =
+                self.disk_keys(&query, &query_neq, exact, &pattern, pattern_exclude);
             let start = Instant::now();
             if result.len() < limit {
-                result.extend(self.internal_find(
+                self.internal_find(
                     positive_keys,
-                    limit - result.len(),
-                    |s: &String| self.is_match(&finder_query, s),
+                    &mut result,
+                    |s: &String| self.is_match(&finder_query, s) && is_pattern(s) && is_level(s),
                     start,
                     time,
-                ));
+                );
             }
             if result.len() < limit {
-                result.extend(self.internal_find(
+                self.internal_find(
                     negative_keys,
-                    limit - result.len(),
+                    &mut result,
                     |s: &String| {
-                        !self.is_match(&finder_query_neq, s) && self.is_match(&finder_query, s)
+                        !self.is_match(&finder_query_neq, s)
+                            && self.is_match(&finder_query, s)
+                            && is_pattern(s)
+                            && is_level(s)
                     },
                     start,
                     time,
-                ));
+                );
             }
         }
+        (result.found(), filter_levels.then(|| levels.get()))
+    }
+
+    /// The keys on disk that may match, those that cannot match the query
+    /// filtering away and those that may.
+    fn disk_keys(
+        &self,
+        query: &str,
+        query_neq: &str,
+        exact: bool,
+        pattern: &Template,
+        pattern_exclude: bool,
+    ) -> (Vec<usize>, Vec<usize>) {
+        let mut positive_keys = self.ser.index.search(query, exact);
+        let mut negative_keys = self.ser.index.search_or(query_neq);
+        if !query_neq.is_empty() {
+            let set: FnvHashSet<usize> = positive_keys.iter().cloned().collect();
+            let set_neg: FnvHashSet<usize> = negative_keys.iter().cloned().collect();
+            negative_keys.retain(|x| set.contains(x));
+            positive_keys.retain(|x| !set_neg.contains(x));
+        }
+        let keywords = pattern.keywords();
+        if !pattern_exclude && !keywords.is_empty() {
+            let set: FnvHashSet<usize> = self
+                .ser
+                .index
+                .search(&keywords.join(" "), false)
+                .into_iter()
+                .collect();
+            positive_keys.retain(|x| set.contains(x));
+            negative_keys.retain(|x| set.contains(x));
+        }
+        (positive_keys, negative_keys)
+    }
+
+    /// Writes every match of the search, pattern and level filters to the
+    /// file, newest first. Matches are read one at a time, not collected.
+    fn export(
+        &self,
+        query: &str,
+        query_neq: &str,
+        exact: bool,
+        path: &Path,
+        pointers: &[String],
+    ) -> io::Result<usize> {
+        let query = query.to_lowercase();
+        let finder_query = Self::get_finder(exact, &query);
+        let query_neq = query_neq.to_lowercase();
+        let finder_query_neq = Self::get_finder(exact, &query_neq);
+        let (pattern, pattern_exclude, level_pointer, hidden_levels) = {
+            let state = GLOBAL_STATE.lock().unwrap();
+            (
+                Template::new(&state.pattern),
+                state.pattern_exclude,
+                state.level_pointer.to_string(),
+                state.hidden_levels.clone(),
+            )
+        };
+        let is_match = |s: &String| {
+            (query_neq.is_empty() || !self.is_match(&finder_query_neq, s))
+                && self.is_match(&finder_query, s)
+                && (pattern.is_empty() || pattern.matches(s) != pattern_exclude)
+                && (hidden_levels.is_empty()
+                    || level(s, &level_pointer).is_none_or(|l| !hidden_levels.contains(&l)))
+        };
+        let (positive_keys, negative_keys) =
+            self.disk_keys(&query, &query_neq, exact, &pattern, pattern_exclude);
+        let negative_keys = match query_neq.is_empty() {
+            true => vec![],
+            false => negative_keys,
+        };
+        let docs = self
+            .ser
+            .lines
+            .values()
+            .rev()
+            .cloned()
+            .chain(
+                positive_keys
+                    .into_iter()
+                    .chain(negative_keys)
+                    .filter_map(|k| self.get(&k).ok()),
+            )
+            .filter(is_match);
+        export::write(path, export::Format::of(path), docs, pointers)
+    }
+
+    /// Documents with the value at the pointer, from any source and whatever
+    /// the search and pattern filter are. The value's text narrows the
+    /// candidates first.
+    fn follow(
+        &self,
+        pointer: &str,
+        value: &str,
+        limit: usize,
+        time: u128,
+    ) -> Vec<(Option<u64>, String)> {
+        let lowercase = value.to_lowercase();
+        let finder = Self::get_finder(true, &lowercase);
+        let at_pointer = |s: &str| resolve_pointer_some(s, pointer).as_deref() == Some(value);
+        let mut result = Collapse::new(limit, None);
+        self.ser
+            .lines
+            .iter()
+            .rev()
+            .filter(|(_, s)| self.is_match(&finder, s) && at_pointer(s))
+            .take_while(|(k, s)| result.push(s.to_string(), self.memory_number(k)))
+            .for_each(drop);
+        if result.len() < limit {
+            self.internal_find(
+                self.ser.index.search(&lowercase, true),
+                &mut result,
+                |s: &String| self.is_match(&finder, s) && at_pointer(s),
+                Instant::now(),
+                time,
+            );
+        }
         result
+            .found()
+            .into_iter()
+            .map(|f| (f.number, f.text))
+            .collect()
+    }
+
+    /// Mines message templates from the newest documents, in memory and then on
+    /// disk, until the time is up. Returns the templates and the documents read.
+    fn patterns(&self, time: u128, since: Option<i128>) -> (Timeline, usize) {
+        let start = Instant::now();
+        let pointers = time_pointers();
+        let mut timeline = Timeline::new(since);
+        let mut mined = 0;
+        self.ser
+            .lines
+            .values()
+            .rev()
+            .map(|s| s.to_string())
+            .chain(
+                self.ser
+                    .index_fd
+                    .keys()
+                    .rev()
+                    .filter_map(|k| self.get(k).ok()),
+            )
+            .take_while(|_| start.elapsed().as_millis() < time)
+            .for_each(|s| {
+                timeline.add(&s, time_of(&s, &pointers));
+                mined += 1;
+            });
+        (timeline, mined)
     }
 
     fn get_finder(exact: bool, query: &str) -> Vec<Finder> {
@@ -257,17 +642,23 @@ impl MemStore {
     fn internal_find(
         &self,
         keys: Vec<usize>,
-        limit: usize,
+        result: &mut Collapse,
         filter: impl Fn(&String) -> bool,
         start: Instant,
         time: u128,
-    ) -> Vec<String> {
+    ) {
         keys.iter()
             .take_while(|_| start.elapsed().as_millis() < time)
-            .map(|x| self.get(x).unwrap())
-            .filter(filter)
-            .take(limit)
-            .collect::<Vec<String>>()
+            .map(|x| (x, self.get(x).unwrap()))
+            .filter(|(_, s)| filter(s))
+            .take_while(|(x, s)| {
+                result.push(s.to_string(), self.order.number(&Location::Disk(**x)))
+            })
+            .for_each(drop);
+    }
+
+    fn memory_number(&self, key: &str) -> Option<u64> {
+        self.order.number(&Location::Memory(key.into()))
     }
 
     fn is_match(&self, needle: &[Finder], s: &str) -> bool {
@@ -284,11 +675,22 @@ impl MemStore {
     fn write(&mut self) {
         let serialized: Vec<u8> = bincode::serialize(&self.ser).unwrap();
         fs::write(".melt.dat", serialized).unwrap();
+        fs::write(ORDER_PATH, bincode::serialize(&self.order).unwrap()).unwrap();
         self.data_fd.sync_all().unwrap()
     }
 
+    /// The ingest order is kept apart from the store, a store without one
+    /// loads with none.
+    fn load_order() -> Order {
+        get_file_as_byte_vec(ORDER_PATH)
+            .ok()
+            .and_then(|f| deserialize::<Order>(&f).ok())
+            .map(Order::loaded)
+            .unwrap_or_default()
+    }
+
     fn load() -> MemStoreSer {
-        let file = get_file_as_byte_vec(&".melt.dat");
+        let file = get_file_as_byte_vec(".melt.dat");
         match file {
             Ok(file) => deserialize(&file).unwrap_or(MemStoreSer {
                 dict: vec![],
@@ -316,6 +718,13 @@ pub async fn search_thread(
     sink: ExtEventSink,
 ) -> JoinHandle<i32> {
     socket_listener(tx_search.clone(), sink.clone());
+    syslog_listener(tx_search.clone());
+    gelf_listener(tx_search.clone());
+    tokio::spawn(http_listener("http", HTTP_ADDRESS, tx_search.clone()));
+    tokio::spawn(http_listener("otlp", OTLP_ADDRESS, tx_search.clone()));
+    if !io::stdin().is_terminal() {
+        stdin_listener(tx_search.clone());
+    }
     let s = sink.clone();
     thread::spawn(move || loop {
         thread::sleep(Duration::from_millis(1000));
@@ -335,87 +744,73 @@ pub async fn search_thread(
     index_tread(rx_search, tx_search, sink.clone())
 }
 
-async fn pods(tx_search: Sender<CommandMessage>) -> Vec<JoinHandle<()>> {
-    let mut handles = vec![];
-    let client = match Client::try_default().await {
-        Ok(c) => c,
-        Err(e) => {
-            println!("{}", e);
-            return handles;
-        }
-    };
-    let pods: Api<Pod> = Api::default_namespaced(client);
-    let x = match pods.list(&ListParams::default()).await {
-        Ok(c) => c,
-        Err(e) => {
-            println!("{}", e);
-            return handles;
-        }
-    };
-    for p in x.items {
-        let name: String = match p.clone().metadata.name {
-            None => {
-                continue;
-            }
-            Some(s) => s,
-        };
-        let mut logs = match pods
-            .log_stream(
-                &name,
-                &LogParams {
-                    follow: true,
-                    ..LogParams::default()
-                },
-            )
-            .await
-        {
-            Ok(c) => c,
+/// Ingest-only mode, reads JSON Lines until EOF straight into the store without
+/// starting the gui, sampled like stdin is while the gui runs. Returns the
+/// number of documents added.
+pub fn ingest(reader: impl BufRead) -> io::Result<usize> {
+    let mut mem_store = MemStore::open()?;
+    let mut multiline = Multiline::default();
+    let mut count = 0;
+    // Lines that are not UTF-8 are read lossily, an error reading still keeps
+    // what was added so far
+    for line in reader.split(b'\n') {
+        let line = match line {
+            Ok(line) => String::from_utf8_lossy(&line)
+                .trim_end_matches('\r')
+                .to_string(),
             Err(e) => {
-                println!("{}", e);
-                continue;
+                mem_store.write();
+                return Err(e);
             }
         };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !record_line("stdin", line.len()) || !keep("stdin", &line) {
+            continue;
+        }
+        for (source, event, tags) in multiline.push("stdin", line, vec![]) {
+            count += mem_store.index(&source, &event, &tags);
+        }
+    }
+    for (source, event, tags) in multiline.flush_all() {
+        count += mem_store.index(&source, &event, &tags);
+    }
+    mem_store.write();
+    Ok(count)
+}
 
-        let sender = tx_search.clone();
-        handles.push(tokio::spawn(async move {
-                sleep(Duration::from_millis(5000)).await;
-            let mut buff = String::new();
-                while let Some(item) = match logs.try_next().await {
-                    Ok(s) => {s}
-                    Err(_) => {return }
-                } {
-
-                    let s = String::from_utf8_lossy(&item).to_string();
-                    buff.push_str(&s);
-                    if !s.ends_with("\n") {
-                        continue
+fn stdin_listener(tx_send: Sender<CommandMessage>) {
+    thread::spawn(move || {
+        let key = "stdin";
+        set_state(key, StreamState::Streaming);
+        for line in io::stdin().lock().split(b'\n') {
+            match line {
+                Ok(s) => {
+                    let s = String::from_utf8_lossy(&s)
+                        .trim_end_matches('\r')
+                        .to_string();
+                    if s.trim().is_empty() {
+                        continue;
                     }
-                    let line = buff.clone();
-                    buff = String::new();
-                    if !line.is_empty() {
-                        let json = match is_valid_json(line.trim_end().trim()) {
-                            true => line.trim_end().trim().to_string(),
-                            false => json!({"pod": &p.clone().metadata.name.unwrap(), "log": s.trim_end().trim()})
-                                .to_string(),
-                        };
-                        match sender.send(CommandMessage::InsertJson(json)) {
-                            Ok(c) => c,
-                            Err(e) => {
-                                println!("{}", e);
-                            }
-                        };
+                    if !record_line(key, s.len()) || !keep(key, &s) {
+                        continue;
+                    }
+                    if tx_send
+                        .send(CommandMessage::InsertJson(key.to_string(), s, vec![]))
+                        .is_err()
+                    {
+                        return;
                     }
                 }
-            }));
-    }
-    handles
-}
-
-fn is_valid_json(s: &str) -> bool {
-    match serde_json::from_str::<Value>(s) {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+                Err(e) => {
+                    set_state(key, StreamState::Error(e.to_string()));
+                    return;
+                }
+            }
+        }
+        set_state(key, StreamState::Ended);
+    });
 }
 
 fn index_tread(
@@ -427,11 +822,28 @@ fn index_tread(
         let mut mem_store = MemStore::open().unwrap();
         GLOBAL_COUNT.store(mem_store.size(), Ordering::SeqCst);
         GLOBAL_DATA_SIZE.store(mem_store.ser.bytes as u64, Ordering::SeqCst);
-        let mut handles = vec![];
+        let mut pod_streams = PodStreams::new(tx_search.clone());
+        let mut multiline = Multiline::default();
         loop {
-            match rx_search.recv() {
+            let events = multiline.expired();
+            if !events.is_empty() {
+                for (source, event, tags) in events {
+                    mem_store.index(&source, &event, &tags);
+                }
+                GLOBAL_DATA_SIZE.store(mem_store.ser.bytes as u64, Ordering::SeqCst);
+                GLOBAL_COUNT.store(mem_store.size(), Ordering::SeqCst);
+            }
+            match rx_search.recv_timeout(Duration::from_millis(100)) {
                 Ok(cm) => match cm {
-                    CommandMessage::Filter(query, neg_query, exact, time, limit, pointer_state) => {
+                    CommandMessage::Filter(
+                        query,
+                        neg_query,
+                        exact,
+                        collapse,
+                        time,
+                        limit,
+                        pointer_state,
+                    ) => {
                         if GLOBAL_STATE.lock().unwrap().query != query
                             && GLOBAL_STATE.lock().unwrap().query_neg != neg_query
                         {
@@ -442,48 +854,107 @@ fn index_tread(
                             data.ongoing_search = true;
                         });
                         let instant = Instant::now();
-                        let result = mem_store.find(
+                        let volatile = pointer_state
+                            .iter()
+                            .filter(|p| p.checked_volatile)
+                            .map(|p| p.text.to_string())
+                            .collect::<Vec<String>>();
+                        let (result, levels) = mem_store.find(
                             query.as_str(),
                             neg_query.as_str(),
                             exact,
                             limit,
                             time as u128,
+                            match collapse {
+                                true => Some(volatile.as_slice()),
+                                false => None,
+                            },
                         );
 
-                        let query_time = format!(
-                            "Query time   {:?}\nResults      {}",
-                            instant.elapsed(),
-                            result.len().to_formatted_string(&Locale::en)
-                        );
+                        let query_time = match collapse {
+                            true => format!(
+                                "Query time   {:?}\nResults      {} collapsed from {}",
+                                instant.elapsed(),
+                                result.len().to_formatted_string(&Locale::en),
+                                result
+                                    .iter()
+                                    .map(|f| f.count)
+                                    .sum::<usize>()
+                                    .to_formatted_string(&Locale::en)
+                            ),
+                            false => format!(
+                                "Query time   {:?}\nResults      {}",
+                                instant.elapsed(),
+                                result.len().to_formatted_string(&Locale::en)
+                            ),
+                        };
 
-                        let mut items: Box<Vector<_>> =
-                            Box::new(result.iter().map(|m| Item::new(m.as_str())).collect());
+                        let level_pointer = GLOBAL_STATE.lock().unwrap().level_pointer.to_string();
+                        let mut items: Box<Vector<_>> = Box::new(
+                            result
+                                .into_iter()
+                                .map(|f| {
+                                    let mut item = Item::new(f.text.as_str());
+                                    item.level = level(&f.text, &level_pointer);
+                                    item.number = f.number;
+                                    item.count = f.count;
+                                    item.first_seen = f.first_seen;
+                                    item.last_seen = f.last_seen;
+                                    item
+                                })
+                                .collect(),
+                        );
 
                         resolve(&mut items, &pointer_state);
+                        let levels = levels.unwrap_or_else(|| {
+                            let mut counts = [0; 4];
+                            items
+                                .iter()
+                                .filter_map(|i| i.level.map(|l| (l, i.count)))
+                                .for_each(|(l, count)| counts[l.index()] += count);
+                            counts
+                        });
 
                         sink.add_idle_callback(move |data: &mut AppState| {
                             data.query_time = query_time.clone();
+                            data.levels
+                                .iter_mut()
+                                .for_each(|c| c.count = levels[c.level.index()].to_string());
                             data.items = *items;
                             data.ongoing_search = false;
                         });
                         sink.submit_command(SEARCH_RESULT, (), Target::Auto)
                             .unwrap();
                     }
-                    CommandMessage::Pod => handles.extend(pods(tx_search.clone()).await),
+                    CommandMessage::Pod(contexts) => pod_streams.attach(contexts).await,
+                    CommandMessage::TailFile(pattern) => {
+                        files::tail_files(pattern, tx_search.clone())
+                    }
+                    // Receivers have nothing to stop, their lines are left out
+                    // while stopped
+                    CommandMessage::StopStream(key) => match key.split(':').next() {
+                        Some("file") => files::stop(&key),
+                        Some("pod") => pod_streams.stop(&key),
+                        _ => set_state(&key, StreamState::Stopped),
+                    },
+                    CommandMessage::RestartStream(key) => match key.split(':').next() {
+                        Some("file") => files::restart(&key, &tx_search),
+                        Some("pod") => pod_streams.restart(&key).await,
+                        _ => set_state(&key, StreamState::Streaming),
+                    },
                     CommandMessage::Quit => {
-                        handles.iter().for_each(|h| h.abort());
+                        pod_streams.stop_all();
+                        files::stop_all();
+                        for (source, event, tags) in multiline.flush_all() {
+                            mem_store.index(&source, &event, &tags);
+                        }
                         mem_store.write();
                         return 0;
                     }
-                    CommandMessage::InsertJson(cm) => {
-                        match resolve_pointer_some(&cm, &GLOBAL_STATE.lock().unwrap().sort) {
-                            None => {
-                                mem_store.add(&Uuid::new_v4().to_string(), &cm);
-                            }
-                            Some(p) => {
-                                mem_store.add(&p, &cm);
-                            }
-                        };
+                    CommandMessage::InsertJson(source, cm, tags) => {
+                        for (source, event, tags) in multiline.push(&source, cm, tags) {
+                            mem_store.index(&source, &event, &tags);
+                        }
                         GLOBAL_DATA_SIZE.store(mem_store.ser.bytes as u64, Ordering::SeqCst);
                         GLOBAL_COUNT.store(mem_store.size(), Ordering::SeqCst);
                     }
@@ -496,8 +967,159 @@ fn index_tread(
                     CommandMessage::RESORT => {
                         mem_store.resort();
                     }
+                    CommandMessage::Export(query, neg_query, exact, pointers, path) => {
+                        let instant = Instant::now();
+                        let info = match mem_store.export(
+                            query.as_str(),
+                            neg_query.as_str(),
+                            exact,
+                            &path,
+                            &pointers,
+                        ) {
+                            Ok(count) => format!(
+                                "Exported     {} documents to {} in {:?}",
+                                count.to_formatted_string(&Locale::en),
+                                path.display(),
+                                instant.elapsed()
+                            ),
+                            Err(e) => format!("Export       failed {}", e),
+                        };
+                        sink.add_idle_callback(move |data: &mut AppState| {
+                            data.export_info = info;
+                        });
+                    }
+                    CommandMessage::Follow(pointer, value, time, limit) => {
+                        let instant = Instant::now();
+                        let docs = mem_store.follow(&pointer, &value, limit, time as u128);
+                        let groups = follow_groups(docs);
+                        let info = format!(
+                            "Following    {} = {}, {} documents from {} services in {:?}",
+                            pointer,
+                            value,
+                            groups
+                                .iter()
+                                .map(|g| g.items.len())
+                                .sum::<usize>()
+                                .to_formatted_string(&Locale::en),
+                            groups.len(),
+                            instant.elapsed()
+                        );
+                        sink.add_idle_callback(move |data: &mut AppState| {
+                            data.follow = groups;
+                            data.follow_info = info;
+                        });
+                    }
+                    CommandMessage::Context(number, n) => {
+                        let (info, items) = match mem_store.context(number, n) {
+                            None => (
+                                "Context      document no longer stored".to_string(),
+                                Vector::new(),
+                            ),
+                            Some((source, documents, position)) => (
+                                format!(
+                                    "Context      {} documents around the document from {}",
+                                    documents.len() - 1,
+                                    source
+                                ),
+                                documents
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(i, (number, text))| {
+                                        let mut item = Item::new(&text);
+                                        item.number = number;
+                                        item.view = match i == position {
+                                            true => format!("> {}", text),
+                                            false => format!("  {}", text),
+                                        };
+                                        item
+                                    })
+                                    .collect::<Vector<Item>>(),
+                            ),
+                        };
+                        sink.add_idle_callback(move |data: &mut AppState| {
+                            data.context = items;
+                            data.context_info = info;
+                        });
+                    }
+                    CommandMessage::Patterns(time, since) => {
+                        let instant = Instant::now();
+                        let since_time = match since.trim().is_empty() {
+                            true => None,
+                            false => match parse_time(&since) {
+                                Some(t) => Some(t),
+                                None => {
+                                    sink.add_idle_callback(move |data: &mut AppState| {
+                                        data.patterns_info = format!(
+                                            "Since {:?} is not an RFC 3339 time or epoch",
+                                            since
+                                        );
+                                    });
+                                    continue;
+                                }
+                            },
+                        };
+                        let (timeline, mined) = mem_store.patterns(time as u128, since_time);
+                        let anomalies = timeline
+                            .anomalies()
+                            .into_iter()
+                            .take(PATTERN_LIMIT)
+                            .map(|a| AnomalyStatus {
+                                template: a.template,
+                                change: match a.change {
+                                    Change::New => "new".to_string(),
+                                    Change::Rate(rate) => format!("{:.1}x", rate),
+                                },
+                                before: a.before.to_formatted_string(&Locale::en),
+                                after: a.after.to_formatted_string(&Locale::en),
+                            })
+                            .collect::<Vector<AnomalyStatus>>();
+                        let since = match timeline.since() {
+                            Some(s) => format!("since {}", rfc3339(s)),
+                            None => "without times".to_string(),
+                        };
+                        // The newest documents are mined first, the time limit
+                        // cuts the baseline short.
+                        let since = match (mined < mem_store.size(), timeline.oldest()) {
+                            (true, Some(oldest)) => format!(
+                                "{}, baseline cut at {} by the time limit",
+                                since,
+                                rfc3339(oldest)
+                            ),
+                            _ => since,
+                        };
+                        let templates = timeline.templates();
+                        let info = format!(
+                            "Patterns     {} from {} of {} documents in {:?}\nAnomalies    {} {}",
+                            templates.len().to_formatted_string(&Locale::en),
+                            mined.to_formatted_string(&Locale::en),
+                            mem_store.size().to_formatted_string(&Locale::en),
+                            instant.elapsed(),
+                            anomalies.len().to_formatted_string(&Locale::en),
+                            since
+                        );
+                        let patterns = templates
+                            .into_iter()
+                            .take(PATTERN_LIMIT)
+                            .map(|(template, count)| PatternStatus {
+                                template,
+                                count: count.to_formatted_string(&Locale::en),
+                            })
+                            .collect::<Vector<PatternStatus>>();
+                        sink.add_idle_callback(move |data: &mut AppState| {
+                            data.patterns = patterns;
+                            data.anomalies = anomalies;
+                            data.patterns_info = info;
+                        });
+                    }
                 },
-                Err(_) => {}
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    for (source, event, tags) in multiline.flush_all() {
+                        mem_store.index(&source, &event, &tags);
+                    }
+                    mem_store.write();
+                    return 0;
+                }
             };
         }
     })
@@ -529,26 +1151,61 @@ fn socket_listener(tx_send: Sender<CommandMessage>, sink: ExtEventSink) {
                         .to_string()
                 );
             }
+            let dropped = sources::dropped();
+            if dropped > 0 {
+                data.count = format!(
+                    "{}\nSampled out  {}",
+                    data.count,
+                    dropped.to_formatted_string(&Locale::en)
+                );
+            }
             data.indexed_data_in_bytes_string = format!(
                 "Data size    {}",
                 human_bytes(GLOBAL_DATA_SIZE.load(Ordering::SeqCst) as f64)
             );
+            if data.show_streams {
+                data.streams = sources::snapshot();
+            }
         });
     });
 
-    let listener = TcpListener::bind("127.0.0.1:7999").unwrap();
+    let key = "socket";
+    let listener = match TcpListener::bind("127.0.0.1:7999") {
+        Ok(l) => l,
+        Err(e) => {
+            set_state(key, StreamState::Error(e.to_string()));
+            return;
+        }
+    };
 
     thread::spawn(move || {
         for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    set_state(key, StreamState::Error(e.to_string()));
+                    continue;
+                }
+            };
             let sender = tx_send.clone();
             // Spawn a new thread to handle the connection
             thread::spawn(move || {
-                let reader = BufReader::new(stream.unwrap());
-                // Read lines from the socket
+                let source = match stream.peer_addr() {
+                    Ok(a) => format!("socket:{}", a),
+                    Err(_) => key.to_string(),
+                };
+                set_state(&source, StreamState::Streaming);
+                let reader = BufReader::new(stream);
+                // Read lines from the socket until it closes or fails
                 for line in reader.lines() {
                     match line {
+                        Ok(s) if !record_line(&source, s.len()) || !keep(&source, &s) => {}
                         Ok(s) => {
-                            match sender.send(CommandMessage::InsertJson(s)) {
+                            match sender.send(CommandMessage::InsertJson(
+                                source.to_string(),
+                                s,
+                                vec![],
+                            )) {
                                 Ok(_) => {}
                                 Err(_) => {
                                     return;
@@ -556,10 +1213,12 @@ fn socket_listener(tx_send: Sender<CommandMessage>, sink: ExtEventSink) {
                             };
                         }
                         Err(e) => {
-                            println!("{}", e)
+                            set_state(&source, StreamState::Error(e.to_string()));
+                            return;
                         }
                     }
                 }
+                set_state(&source, StreamState::Ended);
             });
         }
     });
@@ -567,12 +1226,27 @@ fn socket_listener(tx_send: Sender<CommandMessage>, sink: ExtEventSink) {
 
 #[derive(Clone)]
 pub enum CommandMessage {
-    Filter(String, String, bool, u64, usize, Vector<PointerState>),
+    /// Query, negative query, exact, collapse, time limit, view limit, pointers.
+    Filter(String, String, bool, bool, u64, usize, Vector<PointerState>),
     RESORT,
+    /// Mines message templates for at most the time limit, flagging the new and
+    /// faster ones since the time given, the last 15 minutes when empty.
+    Patterns(u64, String),
+    /// Pointer and value to follow across sources, time limit, view limit.
+    Follow(String, String, u64, usize),
+    /// The documents around a document number in the order of its source.
+    Context(u64, usize),
+    /// Query, negative query, exact, the pointers of the columns and the file to
+    /// write every match to.
+    Export(String, String, bool, Vec<String>, PathBuf),
     Clear,
     Quit,
-    Pod,
-    InsertJson(String),
+    Pod(Vec<String>),
+    TailFile(String),
+    StopStream(String),
+    RestartStream(String),
+    /// The source's status key, the line and the fields to tag its document with.
+    InsertJson(String, String, Tags),
 }
 
 pub enum ResultMessage {
@@ -584,8 +1258,13 @@ fn resolve_pointers(items: &mut Box<Vector<Item>>, pointer_state: &Vector<Pointe
     pointer_state.iter().for_each(|ps| {
         if ps.checked {
             items.iter_mut().for_each(|item| {
-                item.pointers
-                    .push(resolve_pointer(item.text.as_str(), ps.text.as_str()));
+                let resolved = resolve_pointer(item.text.as_str(), ps.text.as_str());
+                item.pointer_states.push(PointerStateItem {
+                    text: ps.text.to_string(),
+                    resolved: resolved.to_string(),
+                    checked: true,
+                });
+                item.pointers.push(resolved);
             });
             empty_pointer = false;
         }
@@ -601,6 +1280,15 @@ fn resolve(items: &mut Box<Vector<Item>>, pointer_state: &Vector<PointerState>)
     } else {
         items.iter_mut().for_each(|i| i.view = i.text.to_string())
     }
+    items.iter_mut().filter(|i| i.count > 1).for_each(|i| {
+        i.view = match i.first_seen.is_empty() {
+            true => format!("{}x  {}", i.count, i.view),
+            false => format!(
+                "{}x  {} .. {}  {}",
+                i.count, i.first_seen, i.last_seen, i.view
+            ),
+        }
+    });
 }
 
 fn resolve_pointer(text: &str, ps: &str) -> String {
@@ -649,10 +1337,245 @@ fn resolve_pointer_some(text: &str, ps: &str) -> Option<String> {
 }
 
 pub fn get_file_as_byte_vec(filename: &str) -> Result<Vec<u8>, Error> {
-    let mut f = File::open(&filename)?;
-    let metadata = fs::metadata(&filename)?;
+    let mut f = File::open(filename)?;
+    let metadata = fs::metadata(filename)?;
     let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer)?;
+    f.read_exact(&mut buffer)?;
 
     Ok(buffer)
 }
+
+/// Adds the tags to a JSON object, keeping any field the document already has.
+/// A line matching a parsing rule of the source or logfmt is parsed first,
+/// anything else is wrapped in an object with the line under `log`.
+pub fn tag(source: &str, line: &str, tags: &[(&str, &str)]) -> String {
+    let mut map = match serde_json::from_str::<Value>(line) {
+        Ok(Value::Object(map)) => map,
+        _ => match parse(source, line) {
+            Some(map) => map,
+            None => {
+                let mut map = Map::new();
+                map.insert("log".to_string(), Value::String(line.to_string()));
+                map
+            }
+        },
+    };
+    tags.iter().for_each(|(k, v)| {
+        map.entry(k.to_string())
+            .or_insert_with(|| Value::String(v.to_string()));
+    });
+    Value::Object(map).to_string()
+}
+
+/// Formats nanoseconds since the unix epoch as an RFC 3339 UTC timestamp.
+pub fn rfc3339(nanos: i128) -> String {
+    let secs = nanos.div_euclid(1_000_000_000);
+    let sub = nanos.rem_euclid(1_000_000_000);
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400);
+    // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        sub
+    )
+}
+
+/// Reads RFC 3339 timestamps as nanoseconds since the unix epoch, a space for
+/// the T and no zone, taken as UTC, are accepted. Numbers are epoch seconds,
+/// millis, micros or nanos depending on their size.
+pub fn parse_time(text: &str) -> Option<i128> {
+    let text = text.trim();
+    if let Ok(epoch) = text.parse::<i128>() {
+        let scale = match epoch.abs() {
+            e if e < 100_000_000_000 => 1_000_000_000,
+            e if e < 100_000_000_000_000 => 1_000_000,
+            e if e < 100_000_000_000_000_000 => 1_000,
+            _ => 1,
+        };
+        return Some(epoch * scale);
+    }
+    if let Some(epoch) = text.parse::<f64>().ok().filter(|e| e.is_finite()) {
+        let scale = match epoch.abs() {
+            e if e < 1e11 => 1e9,
+            e if e < 1e14 => 1e6,
+            _ => 1e3,
+        };
+        return Some((epoch * scale).round() as i128);
+    }
+    let b = text.as_bytes();
+    if b.len() < 19
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || !matches!(b[10], b'T' | b't' | b' ')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return None;
+    }
+    let num = |s: Option<&str>| s?.parse::<i128>().ok();
+    let year = num(text.get(0..4))?;
+    let month = num(text.get(5..7))?;
+    let day = num(text.get(8..10))?;
+    let hour = num(text.get(11..13))?;
+    let minute = num(text.get(14..16))?;
+    let second = num(text.get(17..19))?;
+    let mut rest = text.get(19..)?;
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.chars().take_while(|c| c.is_ascii_digit()).count();
+        nanos = format!("{:0<9}", &fraction[..digits.min(9)])
+            .parse::<i128>()
+            .ok()?;
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ if rest.len() == 5 || rest.len() == 6 => {
+            let (sign, zone) = match (rest.strip_prefix('+'), rest.strip_prefix('-')) {
+                (Some(zone), _) => (1, zone),
+                (_, Some(zone)) => (-1, zone),
+                _ => return None,
+            };
+            sign * (num(zone.get(0..2))? * 3600 + num(zone.get(zone.len() - 2..))? * 60)
+        }
+        _ => return None,
+    };
+    // days_from_civil, http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some((days * 86400 + hour * 3600 + minute * 60 + second - offset) * 1_000_000_000 + nanos)
+}
+
+/// Groups documents by service, the service seen first first, each ordered by
+/// time with the offset from the first document of all.
+fn follow_groups(docs: Vec<(Option<u64>, String)>) -> Vector<FollowGroup> {
+    let pointers = time_pointers();
+    let mut docs = docs
+        .into_iter()
+        .map(|(n, d)| (time_of(&d, &pointers), n, d))
+        .collect::<Vec<(Option<i128>, Option<u64>, String)>>();
+    docs.sort_by_key(|(t, _, _)| t.unwrap_or(i128::MAX));
+    let start = docs.iter().find_map(|(t, _, _)| *t);
+    let mut groups: Vec<FollowGroup> = vec![];
+    for (time, number, doc) in docs {
+        let service = SERVICE_POINTERS
+            .iter()
+            .find_map(|p| resolve_pointer_some(&doc, p))
+            .unwrap_or("unknown".to_string());
+        let mut item = Item::new(&doc);
+        item.number = number;
+        item.view = match (time, start) {
+            (Some(t), Some(s)) => format!(
+                "+{:.3}s  {}  {}",
+                (t - s) as f64 / 1e9,
+                rfc3339(t),
+                message(&doc)
+            ),
+            _ => message(&doc),
+        };
+        match groups.iter_mut().find(|g| g.name == service) {
+            Some(g) => g.items.push_back(item),
+            None => groups.push(FollowGroup {
+                name: service,
+                items: Vector::from(vec![item]),
+            }),
+        }
+    }
+    Vector::from(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i128 = 1_000_000_000;
+
+    #[test]
+    fn loads_a_store_without_the_order() {
+        let mut lines = BTreeMap::new();
+        lines.insert("a".to_string(), r#"{"a":1}"#.to_string());
+        let mut index_fd = BTreeMap::new();
+        index_fd.insert(3, Entry { offset: 8, len: 5 });
+        // The fields of the store as written before the ingest order existed.
+        let baseline = bincode::serialize(&(
+            vec![1u8, 2],
+            lines,
+            index_fd,
+            get_search_index(),
+            12usize,
+            7usize,
+        ))
+        .unwrap();
+        let ser = deserialize::<MemStoreSer>(&baseline).unwrap();
+        assert_eq!(ser.dict, vec![1, 2]);
+        assert_eq!(ser.lines["a"], r#"{"a":1}"#);
+        assert_eq!(ser.index_fd[&3].offset, 8);
+        assert_eq!(ser.bytes, 12);
+        assert_eq!(ser.bytes_internal, 7);
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        assert_eq!(parse_time("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_time("2023-11-14T22:13:20.5Z"),
+            Some(1_700_000_000 * SECOND + 500_000_000)
+        );
+        assert_eq!(
+            parse_time("2023-11-14 23:13:20+01:00"),
+            Some(1_700_000_000 * SECOND)
+        );
+        assert_eq!(
+            parse_time("2023-11-14T21:13:20-0100"),
+            Some(1_700_000_000 * SECOND)
+        );
+        assert_eq!(
+            parse_time("2023-11-14T22:13:20"),
+            Some(1_700_000_000 * SECOND)
+        );
+    }
+
+    #[test]
+    fn parses_epochs() {
+        assert_eq!(parse_time("1700000000"), Some(1_700_000_000 * SECOND));
+        assert_eq!(parse_time("1700000000000"), Some(1_700_000_000 * SECOND));
+        let fraction = parse_time("1700000000.25").unwrap() - 1_700_000_000 * SECOND;
+        // Floats are as precise as a microsecond at this size.
+        assert!((fraction - 250_000_000).abs() < 1_000);
+    }
+
+    #[test]
+    fn rejects_other_text() {
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("yesterday"), None);
+        assert_eq!(parse_time("2023-11-14T22:13:20é0100"), None);
+        assert_eq!(parse_time("2023-11-14T22:13:20ééé"), None);
+        assert_eq!(parse_time("2023-11-14T22:13:20*01:00"), None);
+        assert_eq!(parse_time("2023/11/14T22:13:20Z"), None);
+    }
+
+    #[test]
+    fn round_trips_through_rfc3339() {
+        let nanos = 1_700_000_000 * SECOND + 123_456_789;
+        assert_eq!(parse_time(&rfc3339(nanos)), Some(nanos));
+    }
+}
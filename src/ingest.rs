@@ -0,0 +1,751 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use glob::Pattern;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::index::get_file_as_byte_vec;
+use crate::redact::{RedactRule, Redactor};
+use crate::sampling::{Sampler, SamplingRule};
+use crate::script::{Script, ScriptConfig};
+use crate::transform;
+use crate::transform::Transform;
+
+/// The field keeping the line a document was parsed from.
+pub const RAW: &str = "_raw";
+
+const CONFIG_PATH: &str = ".melt_ingest.json";
+
+/// The ingest configuration with its rules compiled, shared by every source.
+pub static INGEST: Lazy<RwLock<Ingest>> = Lazy::new(|| {
+    let config = get_file_as_byte_vec(CONFIG_PATH)
+        .ok()
+        .and_then(|f| serde_json::from_slice::<IngestConfig>(&f).ok())
+        .unwrap_or_default();
+    RwLock::new(
+        Ingest::new(config).unwrap_or_else(|_| Ingest::new(IngestConfig::default()).unwrap()),
+    )
+});
+
+/// Ingest settings, edited as JSON in the settings view and kept in
+/// `.melt_ingest.json`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestConfig {
+    pub rules: Vec<ParseRule>,
+    pub multiline: Vec<MultilineRule>,
+    pub sampling: Vec<SamplingRule>,
+    pub transforms: Vec<Transform>,
+    pub script: ScriptConfig,
+    pub redact: Vec<RedactRule>,
+}
+
+/// Turns lines from matching sources into documents, the first rule whose
+/// pattern matches a line wins.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParseRule {
+    pub name: String,
+    /// Glob on the source name, `pod:*`, `file:*nginx*`, `socket:*` or `stdin`.
+    pub source: String,
+    /// A regex with named captures or a grok pattern, `%{IPORHOST:client} %{WORD}`.
+    /// `%{NUMBER:bytes:int}` and `%{NUMBER:took:float}` convert the capture.
+    pub pattern: String,
+}
+
+/// Joins the lines of one event, a stack trace, from matching sources. A line
+/// continues the event when it matches `continuation`, or when it does not match
+/// `start` if only that is given. An event ends at `max_lines` or when the source
+/// has been quiet for `timeout_ms`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultilineRule {
+    pub name: String,
+    pub source: String,
+    pub start: String,
+    pub continuation: String,
+    pub max_lines: usize,
+    pub timeout_ms: u64,
+}
+
+impl Default for MultilineRule {
+    fn default() -> Self {
+        MultilineRule {
+            name: "".to_string(),
+            source: "*".to_string(),
+            start: "".to_string(),
+            continuation: "".to_string(),
+            max_lines: 500,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+pub struct Ingest {
+    pub config: IngestConfig,
+    rules: Vec<CompiledRule>,
+    multiline: Vec<CompiledMultiline>,
+    sampler: Sampler,
+    script: Script,
+    redactor: Redactor,
+}
+
+struct CompiledMultiline {
+    source: Pattern,
+    start: Option<Regex>,
+    continuation: Option<Regex>,
+    max_lines: usize,
+    timeout: Duration,
+}
+
+struct CompiledRule {
+    source: Pattern,
+    regex: Regex,
+    conversions: Vec<(String, String)>,
+}
+
+impl Ingest {
+    fn new(config: IngestConfig) -> Result<Self, String> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|r| {
+                let (regex, conversions) =
+                    grok(&r.pattern).map_err(|e| format!("rule {}: {}", r.name, e))?;
+                Ok(CompiledRule {
+                    source: Pattern::new(&r.source)
+                        .map_err(|e| format!("rule {}: {}", r.name, e))?,
+                    regex: Regex::new(&regex).map_err(|e| format!("rule {}: {}", r.name, e))?,
+                    conversions,
+                })
+            })
+            .collect::<Result<Vec<CompiledRule>, String>>()?;
+        let multiline = config
+            .multiline
+            .iter()
+            .map(|r| {
+                let regex = |pattern: &str| match pattern.is_empty() {
+                    true => Ok(None),
+                    false => Regex::new(pattern)
+                        .map(Some)
+                        .map_err(|e| format!("multiline {}: {}", r.name, e)),
+                };
+                if r.start.is_empty() && r.continuation.is_empty() {
+                    return Err(format!(
+                        "multiline {}: needs a start or continuation regex",
+                        r.name
+                    ));
+                }
+                Ok(CompiledMultiline {
+                    source: Pattern::new(&r.source)
+                        .map_err(|e| format!("multiline {}: {}", r.name, e))?,
+                    start: regex(&r.start)?,
+                    continuation: regex(&r.continuation)?,
+                    max_lines: r.max_lines.max(1),
+                    timeout: Duration::from_millis(r.timeout_ms),
+                })
+            })
+            .collect::<Result<Vec<CompiledMultiline>, String>>()?;
+        let sampler = Sampler::new(&config.sampling)?;
+        transform::validate(&config.transforms)?;
+        let script = Script::new(&config.script)?;
+        let redactor = Redactor::new(&config.redact)?;
+        Ok(Ingest {
+            config,
+            rules,
+            multiline,
+            sampler,
+            script,
+            redactor,
+        })
+    }
+
+    /// The rules for the source, every rule without one, then logfmt.
+    fn parse(&self, source: Option<&str>, line: &str) -> Option<Map<String, Value>> {
+        self.rules
+            .iter()
+            .filter(|r| source.is_none_or(|s| r.source.matches(s)))
+            .find_map(|r| r.parse(line))
+            .or_else(|| parse_logfmt(line))
+    }
+
+    fn structure(&self, source: Option<&str>, line: &str) -> String {
+        let trimmed = line.trim();
+        if trimmed.starts_with('{') && serde_json::from_str::<Value>(trimmed).is_ok() {
+            return line.to_string();
+        }
+        match self.parse(source, trimmed) {
+            Some(map) => Value::Object(map).to_string(),
+            None => line.to_string(),
+        }
+    }
+
+    fn keep(&self, source: &str, line: &str) -> bool {
+        let continues = self
+            .multiline
+            .iter()
+            .find(|r| r.covers(source))
+            .is_some_and(|r| r.continues(line));
+        self.sampler.keep(source, line, continues)
+    }
+
+    fn process(&self, source: &str, doc: String) -> Vec<String> {
+        let doc = transform::apply(&self.config.transforms, doc);
+        self.script
+            .run(source, doc)
+            .into_iter()
+            .map(|doc| match self.redactor.is_empty() {
+                true => doc,
+                false => self.redactor.redact(&doc),
+            })
+            .collect()
+    }
+}
+
+impl CompiledRule {
+    fn parse(&self, line: &str) -> Option<Map<String, Value>> {
+        let captures = self.regex.captures(line)?;
+        let mut map = Map::new();
+        for name in self.regex.capture_names().flatten() {
+            let value = match captures.name(name) {
+                None => continue,
+                Some(m) => m.as_str(),
+            };
+            let value = match self.conversions.iter().find(|(n, _)| n == name) {
+                Some((_, t)) if t == "int" => value
+                    .parse::<i64>()
+                    .map(Value::from)
+                    .unwrap_or(Value::from(value)),
+                Some((_, t)) if t == "float" => value
+                    .parse::<f64>()
+                    .map(Value::from)
+                    .unwrap_or(Value::from(value)),
+                _ => Value::from(value),
+            };
+            map.insert(name.to_string(), value);
+        }
+        map.insert(RAW.to_string(), Value::String(line.to_string()));
+        Some(map)
+    }
+}
+
+/// Sources sending whole events, multiline rules only join their documents when
+/// the glob names them, `syslog:*`, not through `*`.
+const RECEIVERS: [&str; 3] = ["http:", "syslog:", "gelf:"];
+
+impl CompiledMultiline {
+    fn covers(&self, source: &str) -> bool {
+        let receiver = |s: &str| RECEIVERS.iter().any(|r| s.starts_with(r));
+        self.source.matches(source) && (!receiver(source) || receiver(self.source.as_str()))
+    }
+
+    fn continues(&self, line: &str) -> bool {
+        match (&self.continuation, &self.start) {
+            (Some(c), _) => c.is_match(line),
+            (None, Some(s)) => !s.is_match(line),
+            (None, None) => false,
+        }
+    }
+}
+
+/// Fields added to the documents of a source, the pod and container of a log.
+pub type Tags = Vec<(String, String)>;
+
+/// A source's event being assembled and the tags it is sent with.
+struct Event {
+    lines: Vec<String>,
+    tags: Tags,
+    last_line: Instant,
+    timeout: Duration,
+}
+
+/// Assembles multiline events per source, lines of sources without a multiline
+/// rule pass straight through.
+#[derive(Default)]
+pub struct Multiline {
+    pending: HashMap<String, Event>,
+}
+
+impl Multiline {
+    /// Adds a line and returns the events it completes, in order.
+    pub fn push(&mut self, source: &str, line: String, tags: Tags) -> Vec<(String, String, Tags)> {
+        let ingest = INGEST.read().unwrap();
+        let rule = match ingest.multiline.iter().find(|r| r.covers(source)) {
+            Some(r) => r,
+            None => {
+                let mut done = self.flush(source);
+                done.push((source.to_string(), line, tags));
+                return done;
+            }
+        };
+        let mut done = vec![];
+        match self.pending.get_mut(source) {
+            Some(event) if rule.continues(&line) => {
+                event.lines.push(line);
+                event.last_line = Instant::now();
+            }
+            _ => {
+                done.extend(self.flush(source));
+                self.pending.insert(
+                    source.to_string(),
+                    Event {
+                        lines: vec![line],
+                        tags,
+                        last_line: Instant::now(),
+                        timeout: rule.timeout,
+                    },
+                );
+            }
+        }
+        if self.pending[source].lines.len() >= rule.max_lines {
+            done.extend(self.flush(source));
+        }
+        done
+    }
+
+    /// Returns the events of sources that have been quiet for their timeout.
+    pub fn expired(&mut self) -> Vec<(String, String, Tags)> {
+        let sources = self
+            .pending
+            .iter()
+            .filter(|(_, e)| e.last_line.elapsed() >= e.timeout)
+            .map(|(s, _)| s.to_string())
+            .collect::<Vec<String>>();
+        self.take(sources)
+    }
+
+    pub fn flush_all(&mut self) -> Vec<(String, String, Tags)> {
+        let sources = self.pending.keys().cloned().collect::<Vec<String>>();
+        self.take(sources)
+    }
+
+    fn flush(&mut self, source: &str) -> Vec<(String, String, Tags)> {
+        self.take(vec![source.to_string()])
+    }
+
+    fn take(&mut self, sources: Vec<String>) -> Vec<(String, String, Tags)> {
+        sources
+            .into_iter()
+            .filter_map(|s| {
+                self.pending
+                    .remove(&s)
+                    .map(|e| (s, e.lines.join("\n"), e.tags))
+            })
+            .collect()
+    }
+}
+
+pub fn config_json() -> String {
+    serde_json::to_string_pretty(&INGEST.read().unwrap().config).unwrap()
+}
+
+/// Compiles and applies the configuration and persists it.
+pub fn set_config_json(json: &str) -> Result<(), String> {
+    let config = serde_json::from_str::<IngestConfig>(json).map_err(|e| e.to_string())?;
+    let ingest = Ingest::new(config)?;
+    fs::write(
+        CONFIG_PATH,
+        serde_json::to_string_pretty(&ingest.config).unwrap(),
+    )
+    .map_err(|e| e.to_string())?;
+    *INGEST.write().unwrap() = ingest;
+    Ok(())
+}
+
+/// Parses lines that are not JSON into documents, returns the line unchanged
+/// when no parser recognizes it.
+pub fn structure(source: &str, line: &str) -> String {
+    INGEST.read().unwrap().structure(Some(source), line)
+}
+
+/// Whether the sampling rules keep a line or document, checked where the
+/// source reads it so dropped lines are never sent to the index.
+pub fn keep(source: &str, line: &str) -> bool {
+    INGEST.read().unwrap().keep(source, line)
+}
+
+/// Runs the transforms, the script and the redaction rules on a document
+/// before it is indexed and stored, returning what is left to index.
+pub fn pipeline(source: &str, doc: String) -> Vec<String> {
+    INGEST.read().unwrap().process(source, doc)
+}
+
+/// The rules for the source first, then logfmt.
+pub fn parse(source: &str, line: &str) -> Option<Map<String, Value>> {
+    INGEST.read().unwrap().parse(Some(source), line)
+}
+
+/// What to preview the rules on for a stored document, which has been through
+/// them already: the line it was parsed from under `_raw`, or the document as
+/// stored when it was JSON to begin with.
+pub fn sample(doc: &str) -> (String, bool) {
+    match serde_json::from_str::<Value>(doc) {
+        Ok(Value::Object(map)) => match map.get(RAW) {
+            Some(Value::String(raw)) => (raw.to_string(), false),
+            _ => (doc.to_string(), true),
+        },
+        _ => (doc.to_string(), false),
+    }
+}
+
+/// Runs a sample line or document through a configuration that is not applied
+/// yet, with the parsing rules of every source and without sampling.
+pub fn preview(config: &str, sample: &str) -> Result<String, String> {
+    let config = serde_json::from_str::<IngestConfig>(config).map_err(|e| e.to_string())?;
+    let ingest = Ingest::new(config)?;
+    let docs = ingest.process("preview", ingest.structure(None, sample));
+    if docs.is_empty() {
+        return Ok("Dropped".to_string());
+    }
+    Ok(docs
+        .iter()
+        .map(|doc| match serde_json::from_str::<Value>(doc) {
+            Ok(v) => serde_json::to_string_pretty(&v).unwrap(),
+            Err(_) => doc.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Parses `level=info msg="started server" dur=12ms` into an object with the
+/// line under `_raw`, bare numbers become numbers. Returns `None` unless the
+/// line is at least two pairs and nothing else, so free text containing a
+/// stray `=` is left alone.
+pub fn parse_logfmt(line: &str) -> Option<Map<String, Value>> {
+    let mut map = Map::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '=' || c == '"' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        // A word or quoted text outside of a pair, this is not logfmt.
+        if key.is_empty() || chars.peek() != Some(&'=') {
+            return None;
+        }
+        chars.next();
+        let value = match chars.peek() {
+            Some('"') => {
+                chars.next();
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(e) => value.push(e),
+                            None => {}
+                        },
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                if !closed {
+                    return None;
+                }
+                Value::String(value)
+            }
+            _ => {
+                let mut value = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                bare_value(value)
+            }
+        };
+        map.insert(key, value);
+    }
+    if map.len() < 2 {
+        return None;
+    }
+    map.insert(RAW.to_string(), Value::String(line.to_string()));
+    Some(map)
+}
+
+/// Numbers with a leading zero or plus sign, ids, zip codes and phone numbers
+/// like `007` and `+47`, stay strings.
+fn bare_value(value: String) -> Value {
+    let digits = value.strip_prefix('-').unwrap_or(&value);
+    if value.starts_with(['+', '.'])
+        || digits.len() > 1 && digits.starts_with('0') && !digits[1..].starts_with('.')
+    {
+        return Value::String(value);
+    }
+    if let Ok(i) = value.parse::<i64>() {
+        return Value::from(i);
+    }
+    match value.parse::<f64>() {
+        Ok(f) if f.is_finite() => Value::from(f),
+        _ => Value::String(value),
+    }
+}
+
+/// Expands grok `%{PATTERN}`, `%{PATTERN:field}` and `%{PATTERN:field:type}`
+/// into a regex, returning it with the requested conversions. A pattern without
+/// grok syntax is used as a regex as is.
+fn grok(pattern: &str) -> Result<(String, Vec<(String, String)>), String> {
+    let mut conversions = vec![];
+    let regex = expand(pattern, &mut conversions, 0)?;
+    Ok((regex, conversions))
+}
+
+fn expand(
+    pattern: &str,
+    conversions: &mut Vec<(String, String)>,
+    depth: usize,
+) -> Result<String, String> {
+    if depth > 16 {
+        return Err(format!("grok pattern nested too deep in {}", pattern));
+    }
+    let mut regex = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find("%{") {
+        regex.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or(format!("unclosed %{{ in {}", pattern))?;
+        let mut parts = rest[start + 2..start + end].splitn(3, ':');
+        let name = parts.next().unwrap_or("");
+        let definition = GROK_PATTERNS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, d)| *d)
+            .ok_or(format!("unknown grok pattern {}", name))?;
+        let inner = expand(definition, conversions, depth + 1)?;
+        match parts.next() {
+            Some(field) => {
+                let field = field
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect::<String>();
+                if let Some(t) = parts.next() {
+                    if !CONVERSIONS.contains(&t) {
+                        return Err(format!("unknown conversion {} of {}", t, field));
+                    }
+                    conversions.push((field.to_string(), t.to_string()));
+                }
+                regex.push_str(&format!("(?P<{}>{})", field, inner));
+            }
+            None => regex.push_str(&format!("(?:{})", inner)),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    regex.push_str(rest);
+    Ok(regex)
+}
+
+/// The types a grok capture can be converted to, `%{NUMBER:bytes:int}`.
+const CONVERSIONS: [&str; 2] = ["int", "float"];
+
+/// A subset of the Logstash grok patterns.
+const GROK_PATTERNS: &[(&str, &str)] = &[
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    ("INT", r"[+-]?[0-9]+"),
+    ("BASE10NUM", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+    ("NUMBER", r"%{BASE10NUM}"),
+    ("POSINT", r"[1-9][0-9]*"),
+    ("NONNEGINT", r"[0-9]+"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*""#),
+    ("QS", r"%{QUOTEDSTRING}"),
+    (
+        "UUID",
+        r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}",
+    ),
+    ("IPV4", r"(?:[0-9]{1,3}\.){3}[0-9]{1,3}"),
+    ("IPV6", r"[0-9A-Fa-f:]*:[0-9A-Fa-f:.]+"),
+    ("IP", r"(?:%{IPV6}|%{IPV4})"),
+    (
+        "HOSTNAME",
+        r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b",
+    ),
+    ("IPORHOST", r"(?:%{IP}|%{HOSTNAME})"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("UNIXPATH", r"(?:/[^/\s?#]*)+"),
+    ("PATH", r"%{UNIXPATH}"),
+    ("URIPROTO", r"[A-Za-z][A-Za-z0-9+.-]*"),
+    ("URIHOST", r"%{IPORHOST}(?::%{POSINT})?"),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+"),
+    ("URIPARAM", r"\?[A-Za-z0-9$.+!*'|(){},~@#%&/=:;_?\-\[\]<>]*"),
+    ("URIPATHPARAM", r"%{URIPATH}(?:%{URIPARAM})?"),
+    (
+        "URI",
+        r"%{URIPROTO}://(?:%{USER}(?::[^@]*)?@)?(?:%{URIHOST})?(?:%{URIPATHPARAM})?",
+    ),
+    (
+        "MONTH",
+        r"\b(?:[Jj]an(?:uary)?|[Ff]eb(?:ruary)?|[Mm]ar(?:ch)?|[Aa]pr(?:il)?|[Mm]ay|[Jj]un(?:e)?|[Jj]ul(?:y)?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo]ct(?:ober)?|[Nn]ov(?:ember)?|[Dd]ec(?:ember)?)\b",
+    ),
+    ("MONTHNUM", r"(?:0?[1-9]|1[0-2])"),
+    ("MONTHDAY", r"(?:0[1-9]|[12][0-9]|3[01]|[1-9])"),
+    ("DAY", r"(?:Mon|Tue|Wed|Thu|Fri|Sat|Sun)[a-z]*"),
+    ("YEAR", r"[0-9]{2,4}"),
+    ("HOUR", r"(?:2[0123]|[01]?[0-9])"),
+    ("MINUTE", r"(?:[0-5][0-9])"),
+    ("SECOND", r"(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?"),
+    ("TIME", r"%{HOUR}:%{MINUTE}(?::%{SECOND})?"),
+    ("DATE_US", r"%{MONTHNUM}[/-]%{MONTHDAY}[/-]%{YEAR}"),
+    ("DATE_EU", r"%{MONTHDAY}[./-]%{MONTHNUM}[./-]%{YEAR}"),
+    ("DATE", r"(?:%{DATE_US}|%{DATE_EU})"),
+    ("ISO8601_TIMEZONE", r"(?:Z|[+-]%{HOUR}(?::?%{MINUTE}))"),
+    (
+        "TIMESTAMP_ISO8601",
+        r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?",
+    ),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    (
+        "LOGLEVEL",
+        r"(?:[Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo|INFO|[Ww]arn(?:ing)?|WARN(?:ING)?|[Ee]rr(?:or)?|ERR(?:OR)?|[Cc]rit(?:ical)?|CRIT(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|[Ee]merg(?:ency)?|EMERG(?:ENCY)?)",
+    ),
+    (
+        "JAVACLASS",
+        r"(?:[a-zA-Z$_][a-zA-Z$_0-9]*\.)*[a-zA-Z$_][a-zA-Z$_0-9]*",
+    ),
+    ("JAVATHREAD", r"[^\]]+"),
+    (
+        "COMMONAPACHELOG",
+        r#"%{IPORHOST:clientip} %{USER:ident} %{USER:auth} \[%{HTTPDATE:timestamp}\] "(?:%{WORD:verb} %{NOTSPACE:request}(?: HTTP/%{NUMBER:httpversion})?|%{DATA:rawrequest})" %{NUMBER:response:int} (?:%{NUMBER:bytes:int}|-)"#,
+    ),
+    (
+        "COMBINEDAPACHELOG",
+        r#"%{COMMONAPACHELOG} %{QS:referrer} %{QS:agent}"#,
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ingest(rules: &[(&str, &str)]) -> Ingest {
+        Ingest::new(IngestConfig {
+            rules: rules
+                .iter()
+                .map(|(source, pattern)| ParseRule {
+                    name: "test".to_string(),
+                    source: source.to_string(),
+                    pattern: pattern.to_string(),
+                })
+                .collect(),
+            ..IngestConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_logfmt() {
+        let map = parse_logfmt(r#"level=info msg="started \"web\"" dur=12 ratio=0.5"#).unwrap();
+        assert_eq!(map["level"], "info");
+        assert_eq!(map["msg"], "started \"web\"");
+        assert_eq!(map["dur"], 12);
+        assert_eq!(map["ratio"], 0.5);
+        assert!(map.contains_key(RAW));
+    }
+
+    #[test]
+    fn leaves_text_that_is_not_logfmt() {
+        assert!(parse_logfmt("a=1").is_none());
+        assert!(parse_logfmt("the user said x=1 and y=2 to the server").is_none());
+        assert!(parse_logfmt(r#"a=1 b="unclosed"#).is_none());
+        assert!(parse_logfmt(r#"a=1 b=2 "quoted""#).is_none());
+        assert!(parse_logfmt("user=a id=b failed").is_none());
+        assert!(parse_logfmt("a=1 =2").is_none());
+    }
+
+    #[test]
+    fn keeps_leading_zeros() {
+        assert_eq!(bare_value("007".to_string()), json!("007"));
+        assert_eq!(bare_value("-01".to_string()), json!("-01"));
+        assert_eq!(bare_value("0".to_string()), json!(0));
+        assert_eq!(bare_value("0.25".to_string()), json!(0.25));
+        assert_eq!(bare_value("-3".to_string()), json!(-3));
+        assert_eq!(bare_value("+3".to_string()), json!("+3"));
+        assert_eq!(bare_value("inf".to_string()), json!("inf"));
+    }
+
+    #[test]
+    fn expands_grok() {
+        let (regex, conversions) = grok("%{INT:code:int} %{WORD}").unwrap();
+        assert_eq!(regex, r"(?P<code>[+-]?[0-9]+) (?:\b\w+\b)");
+        assert_eq!(conversions, vec![("code".to_string(), "int".to_string())]);
+        assert_eq!(grok("plain (?P<a>x)").unwrap().0, "plain (?P<a>x)");
+        assert!(grok("%{NOPE}").is_err());
+        assert!(grok("%{INT").is_err());
+        assert!(grok("%{NUMBER:x:flaot}").is_err());
+    }
+
+    #[test]
+    fn multiline_everywhere_leaves_receivers_alone() {
+        let rule = |source: &str| CompiledMultiline {
+            source: Pattern::new(source).unwrap(),
+            start: None,
+            continuation: Some(Regex::new("^ ").unwrap()),
+            max_lines: 10,
+            timeout: Duration::from_secs(1),
+        };
+        assert!(rule("*").covers("pod:web"));
+        assert!(rule("*").covers("stdin"));
+        assert!(!rule("*").covers("syslog:udp"));
+        assert!(!rule("*").covers("http:otlp"));
+        assert!(rule("gelf:*").covers("gelf:tcp"));
+    }
+
+    #[test]
+    fn samples_the_line_before_the_rules() {
+        assert_eq!(
+            sample(r#"{"a":1,"_raw":"a=1 b=2"}"#),
+            ("a=1 b=2".to_string(), false)
+        );
+        assert_eq!(sample(r#"{"a":1}"#), (r#"{"a":1}"#.to_string(), true));
+        assert_eq!(sample("plain"), ("plain".to_string(), false));
+    }
+
+    #[test]
+    fn structures_with_the_rules_of_the_source() {
+        let ingest = ingest(&[("file:*nginx*", "%{COMBINEDAPACHELOG}")]);
+        let line =
+            r#"127.0.0.1 - - [10/Oct/2023:13:55:36 +0000] "GET /a HTTP/1.1" 200 512 "-" "curl""#;
+        let doc =
+            serde_json::from_str::<Value>(&ingest.structure(Some("file:nginx.log"), line)).unwrap();
+        assert_eq!(doc["clientip"], "127.0.0.1");
+        assert_eq!(doc["verb"], "GET");
+        assert_eq!(doc["response"], 200);
+        assert_eq!(doc["bytes"], 512);
+        assert_eq!(doc[RAW], line);
+
+        assert_eq!(ingest.structure(Some("stdin"), line), line);
+        assert_eq!(ingest.structure(Some("stdin"), r#"{"a":1}"#), r#"{"a":1}"#);
+        let doc =
+            serde_json::from_str::<Value>(&ingest.structure(Some("stdin"), "a=1 b=x")).unwrap();
+        assert_eq!(doc["b"], "x");
+    }
+}
@@ -0,0 +1,211 @@
+use druid::Data;
+use serde_json::Value;
+
+const ERROR_LEVELS: [&str; 10] = [
+    "error",
+    "err",
+    "fatal",
+    "crit",
+    "critical",
+    "alert",
+    "emerg",
+    "emergency",
+    "panic",
+    "severe",
+];
+const WARN_LEVELS: [&str; 2] = ["warn", "warning"];
+const INFO_LEVELS: [&str; 4] = ["info", "information", "informational", "notice"];
+const DEBUG_LEVELS: [&str; 4] = ["debug", "dbg", "trace", "verbose"];
+const LEVEL_POINTERS: [&str; 7] = [
+    "/level",
+    "/severity",
+    "/severity_text",
+    "/lvl",
+    "/loglevel",
+    "/log/level",
+    "/log.level",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Data)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::Error, Level::Warn, Level::Info, Level::Debug];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    fn of_name(name: &str) -> Option<Level> {
+        let name = name.to_lowercase();
+        [
+            (&ERROR_LEVELS[..], Level::Error),
+            (&WARN_LEVELS[..], Level::Warn),
+            (&INFO_LEVELS[..], Level::Info),
+            (&DEBUG_LEVELS[..], Level::Debug),
+        ]
+        .iter()
+        .find(|(names, _)| names.contains(&name.as_str()))
+        .map(|(_, level)| *level)
+    }
+
+    /// Syslog severities count down from debug at 7 to emerg at 0.
+    fn of_syslog(code: u64) -> Option<Level> {
+        match code {
+            0..=3 => Some(Level::Error),
+            4 => Some(Level::Warn),
+            5 | 6 => Some(Level::Info),
+            7 => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    /// OTLP severity numbers count up from trace at 1 to fatal at 24.
+    fn of_otlp(number: u64) -> Option<Level> {
+        match number {
+            1..=8 => Some(Level::Debug),
+            9..=12 => Some(Level::Info),
+            13..=16 => Some(Level::Warn),
+            17..=24 => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    /// A level field holding a number, pino and bunyan numbers from 10 up and
+    /// syslog severities below.
+    fn of_number(number: u64) -> Option<Level> {
+        match number {
+            0..=7 => Level::of_syslog(number),
+            10..=29 => Some(Level::Debug),
+            30..=39 => Some(Level::Info),
+            40..=49 => Some(Level::Warn),
+            50.. => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    fn of_value(value: &Value) -> Option<Level> {
+        match value {
+            Value::String(s) => Level::of_name(s),
+            Value::Number(n) => n.as_u64().and_then(Level::of_number),
+            _ => None,
+        }
+    }
+}
+
+/// The level of a document, from the pointer when given and found, otherwise
+/// from the usual level fields, syslog and OTLP severities, and for lines that
+/// are not JSON the text itself.
+pub fn level(doc: &str, pointer: &str) -> Option<Level> {
+    let value = match serde_json::from_str::<Value>(doc) {
+        Ok(v @ Value::Object(_)) => v,
+        _ => return level_of_text(doc),
+    };
+    if !pointer.is_empty() {
+        if let Some(level) = value.pointer(pointer).and_then(Level::of_value) {
+            return Some(level);
+        }
+    }
+    if let Some(code) = value.pointer("/severity_code").and_then(|c| c.as_u64()) {
+        return Level::of_syslog(code);
+    }
+    if let Some(number) = value.pointer("/severity_number").and_then(|c| c.as_u64()) {
+        return Level::of_otlp(number);
+    }
+    LEVEL_POINTERS
+        .iter()
+        .filter_map(|p| value.pointer(p))
+        .find_map(Level::of_value)
+}
+
+fn level_of_text(doc: &str) -> Option<Level> {
+    if ["ERROR", "FATAL", "Exception", "panic"]
+        .iter()
+        .any(|e| doc.contains(e))
+    {
+        return Some(Level::Error);
+    }
+    [
+        ("WARN", Level::Warn),
+        ("INFO", Level::Info),
+        ("DEBUG", Level::Debug),
+        ("TRACE", Level::Debug),
+    ]
+    .iter()
+    .find(|(word, _)| doc.contains(word))
+    .map(|(_, level)| *level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_level_names() {
+        assert_eq!(Level::of_name("ERR"), Some(Level::Error));
+        assert_eq!(Level::of_name("Warning"), Some(Level::Warn));
+        assert_eq!(Level::of_name("notice"), Some(Level::Info));
+        assert_eq!(Level::of_name("trace"), Some(Level::Debug));
+        assert_eq!(Level::of_name("loud"), None);
+    }
+
+    #[test]
+    fn reads_level_numbers() {
+        assert_eq!(Level::of_syslog(0), Some(Level::Error));
+        assert_eq!(Level::of_syslog(4), Some(Level::Warn));
+        assert_eq!(Level::of_syslog(6), Some(Level::Info));
+        assert_eq!(Level::of_syslog(8), None);
+        assert_eq!(Level::of_otlp(0), None);
+        assert_eq!(Level::of_otlp(5), Some(Level::Debug));
+        assert_eq!(Level::of_otlp(9), Some(Level::Info));
+        assert_eq!(Level::of_otlp(13), Some(Level::Warn));
+        assert_eq!(Level::of_otlp(21), Some(Level::Error));
+        assert_eq!(Level::of_otlp(25), None);
+        assert_eq!(Level::of_number(3), Some(Level::Error));
+        assert_eq!(Level::of_number(8), None);
+        assert_eq!(Level::of_number(20), Some(Level::Debug));
+        assert_eq!(Level::of_number(30), Some(Level::Info));
+        assert_eq!(Level::of_number(40), Some(Level::Warn));
+        assert_eq!(Level::of_number(60), Some(Level::Error));
+    }
+
+    #[test]
+    fn finds_the_level_of_a_document() {
+        assert_eq!(level(r#"{"level":"warn"}"#, ""), Some(Level::Warn));
+        assert_eq!(level(r#"{"lvl":30}"#, ""), Some(Level::Info));
+        assert_eq!(
+            level(r#"{"sev":"error","level":"info"}"#, "/sev"),
+            Some(Level::Error)
+        );
+        assert_eq!(
+            level(r#"{"sev":"loud","level":"info"}"#, "/sev"),
+            Some(Level::Info)
+        );
+        assert_eq!(level(r#"{"severity_code":7}"#, ""), Some(Level::Debug));
+        assert_eq!(level(r#"{"severity_number":17}"#, ""), Some(Level::Error));
+        assert_eq!(level(r#"{"message":"hi"}"#, ""), None);
+    }
+
+    #[test]
+    fn finds_the_level_of_text() {
+        assert_eq!(level("java.lang.Exception: no", ""), Some(Level::Error));
+        assert_eq!(level("[WARN] slow", ""), Some(Level::Warn));
+        assert_eq!(level("TRACE enter", ""), Some(Level::Debug));
+        assert_eq!(level("[1, 2]", ""), None);
+        assert_eq!(level("plain", ""), None);
+    }
+}
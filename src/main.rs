@@ -8,9 +8,9 @@
 #![allow(dead_code, non_upper_case_globals)]
 #![windows_subsystem = "windows"]
 
+use std::io;
 use std::sync::Mutex;
 
-use bincode::deserialize;
 use crossbeam_channel::bounded;
 use druid::im::Vector;
 use druid::{AppLauncher, WindowDesc, WindowState};
@@ -20,16 +20,34 @@ use serde as _;
 use data::AppState;
 use view::build_ui;
 
-use crate::data::SerializableParameters;
+use crate::data::{columns, level_chips, SerializableParameters};
 use crate::delegate::Delegate;
-use crate::index::{get_file_as_byte_vec, search_thread, CommandMessage};
+use crate::index::{get_file_as_byte_vec, ingest, search_thread, CommandMessage};
+use crate::level::Level;
+use crate::pods::kube_contexts;
 
 mod data;
 
 mod view;
 
 mod delegate;
+mod export;
+mod files;
+mod gelf;
+mod http;
 mod index;
+mod ingest;
+mod level;
+mod order;
+mod otlp;
+mod patterns;
+mod pods;
+mod redact;
+mod sampling;
+mod script;
+mod sources;
+mod syslog;
+mod transform;
 
 pub struct GlobalState {
     query: String,
@@ -38,6 +56,13 @@ pub struct GlobalState {
     sort: String,
     tail: bool,
     exact: bool,
+    /// A message template searches are limited to, or exclude.
+    pattern: String,
+    pattern_exclude: bool,
+    /// Where the level of a document is, the usual level fields when empty.
+    level_pointer: String,
+    /// Levels filtered away, documents without a level are always shown.
+    hidden_levels: Vec<Level>,
 }
 
 impl Default for GlobalState {
@@ -49,6 +74,10 @@ impl Default for GlobalState {
             sort: "".to_string(),
             tail: false,
             exact: false,
+            pattern: "".to_string(),
+            pattern_exclude: false,
+            level_pointer: "".to_string(),
+            hidden_levels: vec![],
         }
     }
 }
@@ -58,8 +87,24 @@ pub static GLOBAL_STATE: Lazy<Mutex<GlobalState>> =
 
 #[tokio::main]
 async fn main() -> () {
+    if std::env::args().any(|a| a == "--ingest") {
+        attach_console();
+        load_from_json();
+        match ingest(io::stdin().lock()) {
+            Ok(count) => println!(
+                "Ingested {} documents, {} sampled out",
+                count,
+                sources::dropped()
+            ),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     let main_window = WindowDesc::new(build_ui())
-        .title("Melt listening on socket://localhost:7999 expected format is JSON Lines https://jsonlines.org")
+        .title("Melt listening on socket://localhost:7999 expected format is JSON Lines https://jsonlines.org, syslog on udp/tcp port 5514, gelf on udp/tcp port 12201, http on http://localhost:7998, otlp on http://localhost:4318")
         .window_size((1024.0, 768.0))
         .set_window_state(WindowState::Maximized);
     let (tx_search, rx_search) = bounded(0);
@@ -68,6 +113,7 @@ async fn main() -> () {
     let sink = launcher.get_external_handle();
     let parameters = load_from_json();
     let handle = search_thread(rx_search, tx_search.clone(), sink).await;
+    let pointers = Vector::from(parameters.pointer_state);
     launcher
         .delegate(Delegate {})
         .launch(AppState {
@@ -76,10 +122,23 @@ async fn main() -> () {
             viewlimit: 100.0,
             not_query: "".to_string(),
             exact: false,
+            collapse: false,
+            table: false,
             items: Default::default(),
             items_rich: Default::default(),
+            selected: None,
+            export_info: "".to_string(),
             view: "".to_string(),
-            pointers: Vector::from(parameters.pointer_state),
+            tree: Default::default(),
+            view_pointers: Default::default(),
+            follow: Default::default(),
+            follow_info: "".to_string(),
+            view_number: None,
+            context_size: 10.0,
+            context: Default::default(),
+            context_info: "".to_string(),
+            columns: columns(&pointers, &Vector::new()),
+            pointers,
             pointers_view: Vector::from(parameters.pointer_state_view),
             query_time: "".to_string(),
             count: "0".to_string(),
@@ -88,6 +147,23 @@ async fn main() -> () {
             ongoing_search: false,
             properties: Default::default(),
             tail: false,
+            contexts: Vector::from(kube_contexts()),
+            file_pattern: "".to_string(),
+            show_streams: false,
+            streams: Default::default(),
+            show_patterns: false,
+            patterns: Default::default(),
+            patterns_info: "".to_string(),
+            anomalies: Default::default(),
+            anomaly_since: "".to_string(),
+            pattern_filter: "".to_string(),
+            levels: level_chips(),
+            level_pointer: parameters.level_pointer.to_string(),
+            ingest_config: ingest::config_json(),
+            ingest_error: "".to_string(),
+            ingest_sample: "".to_string(),
+            ingest_sample_processed: false,
+            ingest_preview: "".to_string(),
             tx: tx_search.clone(),
         })
         .expect("Failed to launch application");
@@ -97,15 +173,28 @@ async fn main() -> () {
     handle.await.unwrap();
 }
 
+/// The windows build has no console of its own, the ingest-only mode prints to
+/// the one it was started from.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
 pub fn load_from_json() -> SerializableParameters {
     //   let buf = dirs::home_dir().unwrap().into_os_string().into_string().unwrap();
     let path = ".melt_state.dat";
     let file = get_file_as_byte_vec(&path);
     match file {
         Ok(file) => {
-            let parameters: SerializableParameters =
-                deserialize(&file).unwrap_or(SerializableParameters::default());
+            let parameters = SerializableParameters::from_bytes(&file).unwrap_or_default();
             GLOBAL_STATE.lock().unwrap().sort = parameters.sort.to_string();
+            GLOBAL_STATE.lock().unwrap().level_pointer = parameters.level_pointer.to_string();
             parameters
         }
         Err(_) => SerializableParameters::default(),
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+/// Documents numbered past this many are forgotten oldest first, they are
+/// still stored but have no context.
+const MAX_DOCUMENTS: usize = 2_000_000;
+
+/// Where a document is kept, in memory under its store key, the sort key, or on
+/// disk under its index key. Both maps of the order share a key.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Location {
+    Memory(Arc<str>),
+    Disk(usize),
+}
+
+/// The documents of every source in ingest order. Documents are numbered as
+/// they are added, the number stays the same when a document moves to disk or
+/// is resorted. Kept in `.melt_order.dat` next to the store, which loads
+/// without it.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Order {
+    next: u64,
+    /// The oldest number that may still be known.
+    first: u64,
+    /// Source names by id.
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    documents: HashMap<u64, (u32, Location)>,
+    /// The number of each location, rebuilt from the documents when loaded.
+    #[serde(skip)]
+    numbers: HashMap<Location, u64>,
+    /// The numbers of each source by id, oldest first.
+    sources: Vec<VecDeque<u64>>,
+}
+
+impl Order {
+    pub fn add(&mut self, source: &str, location: Location) {
+        let number = self.next;
+        self.next += 1;
+        // An equal sort key replaces the document in memory
+        if let Some(replaced) = self.numbers.insert(location.clone(), number) {
+            self.forget(replaced);
+        }
+        let id = self.id(source);
+        self.documents.insert(number, (id, location));
+        self.sources[id as usize].push_back(number);
+        self.prune(MAX_DOCUMENTS);
+    }
+
+    /// Shares the keys of the documents with the numbers again after loading.
+    pub fn loaded(mut self) -> Self {
+        self.numbers = self
+            .documents
+            .iter()
+            .map(|(number, (_, location))| (location.clone(), *number))
+            .collect();
+        self
+    }
+
+    fn forget(&mut self, number: u64) {
+        if let Some((id, _)) = self.documents.remove(&number) {
+            let numbers = &mut self.sources[id as usize];
+            if let Ok(i) = numbers.binary_search(&number) {
+                numbers.remove(i);
+            }
+        }
+    }
+
+    fn id(&mut self, source: &str) -> u32 {
+        if let Some(id) = self.ids.get(source) {
+            return *id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(source.to_string());
+        self.ids.insert(source.to_string(), id);
+        self.sources.push(VecDeque::new());
+        id
+    }
+
+    /// Forgets the oldest documents over `max`.
+    fn prune(&mut self, max: usize) {
+        while self.documents.len() > max {
+            let number = self.first;
+            self.first += 1;
+            let (id, location) = match self.documents.remove(&number) {
+                None => continue,
+                Some(d) => d,
+            };
+            if self.numbers.get(&location) == Some(&number) {
+                self.numbers.remove(&location);
+            }
+            let numbers = &mut self.sources[id as usize];
+            if numbers.front() == Some(&number) {
+                numbers.pop_front();
+            }
+        }
+    }
+
+    /// Moves documents, all are taken out before any is put back so they can
+    /// swap places. Documents moved to the same sort key replace each other in
+    /// order, as they do in the store.
+    pub fn moved(&mut self, moves: Vec<(Location, Location)>) {
+        let numbers = moves
+            .into_iter()
+            .filter_map(|(from, to)| self.numbers.remove(&from).map(|n| (n, to)))
+            .collect::<Vec<(u64, Location)>>();
+        for (number, to) in numbers {
+            if let Some(replaced) = self.numbers.insert(to.clone(), number) {
+                self.forget(replaced);
+            }
+            if let Some((_, location)) = self.documents.get_mut(&number) {
+                *location = to;
+            }
+        }
+    }
+
+    pub fn number(&self, location: &Location) -> Option<u64> {
+        self.numbers.get(location).copied()
+    }
+
+    /// The source of a document and the locations of up to `n` documents of
+    /// it before and after, with the position of the document among them.
+    pub fn context(&self, number: u64, n: usize) -> Option<(String, Vec<Location>, usize)> {
+        let (id, _) = self.documents.get(&number)?;
+        let numbers = &self.sources[*id as usize];
+        let position = numbers.binary_search(&number).ok()?;
+        let start = position.saturating_sub(n);
+        let end = (position + n + 1).min(numbers.len());
+        let locations = numbers
+            .range(start..end)
+            .filter_map(|n| self.documents.get(n).map(|(_, l)| l.clone()))
+            .collect::<Vec<Location>>();
+        Some((
+            self.names[*id as usize].to_string(),
+            locations,
+            position - start,
+        ))
+    }
+
+    pub fn clear(&mut self) {
+        *self = Order::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(key: &str) -> Location {
+        Location::Memory(key.into())
+    }
+
+    #[test]
+    fn keeps_each_source_in_ingest_order() {
+        let mut order = Order::default();
+        order.add("pod:a", memory("3"));
+        order.add("pod:b", memory("1"));
+        order.add("pod:a", memory("2"));
+        order.add("pod:a", memory("0"));
+        let (source, locations, position) = order.context(2, 1).unwrap();
+        assert_eq!(source, "pod:a");
+        assert!(locations == vec![memory("3"), memory("2"), memory("0")]);
+        assert_eq!(position, 1);
+    }
+
+    #[test]
+    fn follows_moves_and_replacements() {
+        let mut order = Order::default();
+        order.add("stdin", memory("a"));
+        order.add("stdin", memory("b"));
+        order.moved(vec![(memory("a"), memory("b")), (memory("b"), memory("a"))]);
+        assert_eq!(order.number(&memory("a")), Some(1));
+        assert_eq!(order.number(&memory("b")), Some(0));
+        order.moved(vec![(memory("a"), Location::Disk(7))]);
+        assert_eq!(order.number(&Location::Disk(7)), Some(1));
+
+        order.add("file:x", memory("b"));
+        assert_eq!(order.number(&memory("b")), Some(2));
+        let (_, locations, _) = order.context(1, 5).unwrap();
+        assert!(locations == vec![Location::Disk(7)]);
+        assert!(order.context(0, 5).is_none());
+    }
+
+    #[test]
+    fn forgets_the_oldest_documents() {
+        let mut order = Order::default();
+        order.add("stdin", memory("a"));
+        order.add("stdin", memory("a"));
+        (0..4).for_each(|i| order.add("stdin", Location::Disk(i)));
+        order.prune(3);
+        assert_eq!(order.documents.len(), 3);
+        assert_eq!(order.number(&memory("a")), None);
+        assert_eq!(order.number(&Location::Disk(0)), None);
+        assert_eq!(order.number(&Location::Disk(1)), Some(3));
+        assert_eq!(order.sources[0].front(), Some(&3));
+        assert!(order.context(3, 1).is_some());
+    }
+
+    #[test]
+    fn resorts_onto_equal_keys() {
+        let mut order = Order::default();
+        order.add("stdin", memory("a"));
+        order.add("stdin", memory("b"));
+        order.add("stdin", memory("c"));
+        order.moved(vec![
+            (memory("a"), memory("x")),
+            (memory("b"), memory("x")),
+            (memory("c"), memory("y")),
+        ]);
+        assert_eq!(order.number(&memory("x")), Some(1));
+        assert_eq!(order.number(&memory("y")), Some(2));
+        assert_eq!(order.documents.len(), 2);
+        assert!(order.sources[0] == [1, 2]);
+        assert!(order.context(0, 1).is_none());
+        let (_, locations, position) = order.context(1, 1).unwrap();
+        assert!(locations == vec![memory("x"), memory("y")]);
+        assert_eq!(position, 0);
+    }
+
+    #[test]
+    fn shares_the_keys_again_when_loaded() {
+        let mut order = Order::default();
+        order.add("stdin", memory("a"));
+        order.add("stdin", Location::Disk(3));
+        let order = bincode::deserialize::<Order>(&bincode::serialize(&order).unwrap())
+            .unwrap()
+            .loaded();
+        assert_eq!(order.number(&memory("a")), Some(0));
+        assert_eq!(order.number(&Location::Disk(3)), Some(1));
+        let shared = match (
+            &order.documents[&0].1,
+            order.numbers.keys().find(|l| order.numbers[*l] == 0),
+        ) {
+            (Location::Memory(a), Some(Location::Memory(b))) => Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        assert!(shared);
+    }
+}
@@ -0,0 +1,409 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use prost::Message;
+use serde_json::{Map, Value};
+
+use crate::index::rfc3339;
+
+/// Converts an OTLP/HTTP JSON `ExportLogsServiceRequest` into documents.
+pub fn logs_json(body: &[u8]) -> Result<Vec<String>, String> {
+    let request = serde_json::from_slice::<Value>(body).map_err(|e| e.to_string())?;
+    let mut docs = vec![];
+    for resource_logs in array(&request["resourceLogs"]) {
+        let resource = attributes_json(&resource_logs["resource"]["attributes"]);
+        for scope_logs in array(&resource_logs["scopeLogs"]) {
+            let scope = &scope_logs["scope"];
+            let scope = Scope {
+                name: scope["name"].as_str().unwrap_or("").to_string(),
+                version: scope["version"].as_str().unwrap_or("").to_string(),
+                attributes: attributes_json(&scope["attributes"]),
+            };
+            for record in array(&scope_logs["logRecords"]) {
+                docs.push(doc(
+                    &resource,
+                    &scope,
+                    Record {
+                        time_unix_nano: nanos_json(&record["timeUnixNano"]),
+                        observed_time_unix_nano: nanos_json(&record["observedTimeUnixNano"]),
+                        severity_number: record["severityNumber"].as_i64().unwrap_or(0),
+                        severity_text: record["severityText"].as_str().unwrap_or("").to_string(),
+                        body: any_value_json(&record["body"]),
+                        attributes: attributes_json(&record["attributes"]),
+                        trace_id: record["traceId"].as_str().unwrap_or("").to_lowercase(),
+                        span_id: record["spanId"].as_str().unwrap_or("").to_lowercase(),
+                    },
+                ));
+            }
+        }
+    }
+    Ok(docs)
+}
+
+/// Converts an OTLP/HTTP protobuf `ExportLogsServiceRequest` into documents.
+pub fn logs_protobuf(body: &[u8]) -> Result<Vec<String>, String> {
+    let request = ExportLogsServiceRequest::decode(body).map_err(|e| e.to_string())?;
+    let mut docs = vec![];
+    for resource_logs in request.resource_logs {
+        let resource = attributes(
+            resource_logs
+                .resource
+                .map(|r| r.attributes)
+                .unwrap_or(vec![]),
+        );
+        for scope_logs in resource_logs.scope_logs {
+            let scope = match scope_logs.scope {
+                None => Scope {
+                    name: "".to_string(),
+                    version: "".to_string(),
+                    attributes: Map::new(),
+                },
+                Some(s) => Scope {
+                    name: s.name,
+                    version: s.version,
+                    attributes: attributes(s.attributes),
+                },
+            };
+            for record in scope_logs.log_records {
+                docs.push(doc(
+                    &resource,
+                    &scope,
+                    Record {
+                        time_unix_nano: record.time_unix_nano,
+                        observed_time_unix_nano: record.observed_time_unix_nano,
+                        severity_number: record.severity_number as i64,
+                        severity_text: record.severity_text,
+                        body: record.body.map(any_value).unwrap_or(Value::Null),
+                        attributes: attributes(record.attributes),
+                        trace_id: hex(&record.trace_id),
+                        span_id: hex(&record.span_id),
+                    },
+                ));
+            }
+        }
+    }
+    Ok(docs)
+}
+
+struct Scope {
+    name: String,
+    version: String,
+    attributes: Map<String, Value>,
+}
+
+struct Record {
+    time_unix_nano: u64,
+    observed_time_unix_nano: u64,
+    severity_number: i64,
+    severity_text: String,
+    body: Value,
+    attributes: Map<String, Value>,
+    trace_id: String,
+    span_id: String,
+}
+
+/// Resource, scope and record attributes become top level fields under their
+/// own keys, record attributes winning over scope and resource ones, and the
+/// record fields winning over all of them.
+fn doc(resource: &Map<String, Value>, scope: &Scope, record: Record) -> String {
+    let mut map = resource.clone();
+    map.extend(scope.attributes.clone());
+    if !scope.name.is_empty() {
+        map.insert(
+            "scope.name".to_string(),
+            Value::from(scope.name.to_string()),
+        );
+    }
+    if !scope.version.is_empty() {
+        map.insert(
+            "scope.version".to_string(),
+            Value::from(scope.version.to_string()),
+        );
+    }
+    map.extend(record.attributes);
+    let nanos = match record.time_unix_nano {
+        0 => record.observed_time_unix_nano,
+        n => n,
+    };
+    if nanos != 0 {
+        map.insert("timestamp".to_string(), Value::from(rfc3339(nanos as i128)));
+    }
+    if let Some(service) = resource.get("service.name") {
+        map.insert("service".to_string(), service.clone());
+    }
+    if !record.severity_text.is_empty() {
+        map.insert(
+            "severity_text".to_string(),
+            Value::from(record.severity_text),
+        );
+    }
+    if record.severity_number != 0 {
+        map.insert(
+            "severity_number".to_string(),
+            Value::from(record.severity_number),
+        );
+    }
+    if !record.body.is_null() {
+        map.insert("body".to_string(), record.body);
+    }
+    if !record.trace_id.is_empty() {
+        map.insert("trace_id".to_string(), Value::from(record.trace_id));
+    }
+    if !record.span_id.is_empty() {
+        map.insert("span_id".to_string(), Value::from(record.span_id));
+    }
+    Value::Object(map).to_string()
+}
+
+fn array(value: &Value) -> Vec<Value> {
+    value.as_array().cloned().unwrap_or(vec![])
+}
+
+/// 64 bit integers are strings in OTLP/JSON, accept numbers as well.
+fn nanos_json(value: &Value) -> u64 {
+    match value {
+        Value::String(s) => s.parse().unwrap_or(0),
+        v => v.as_u64().unwrap_or(0),
+    }
+}
+
+fn attributes_json(value: &Value) -> Map<String, Value> {
+    array(value)
+        .iter()
+        .filter_map(|kv| {
+            kv["key"]
+                .as_str()
+                .map(|k| (k.to_string(), any_value_json(&kv["value"])))
+        })
+        .collect()
+}
+
+fn any_value_json(value: &Value) -> Value {
+    let map = match value.as_object() {
+        None => return Value::Null,
+        Some(m) => m,
+    };
+    if let Some(v) = map.get("stringValue") {
+        v.clone()
+    } else if let Some(v) = map.get("boolValue") {
+        v.clone()
+    } else if let Some(v) = map.get("intValue") {
+        match v {
+            Value::String(s) => s.parse::<i64>().map(Value::from).unwrap_or(v.clone()),
+            v => v.clone(),
+        }
+    } else if let Some(v) = map.get("doubleValue") {
+        v.clone()
+    } else if let Some(v) = map.get("arrayValue") {
+        Value::Array(array(&v["values"]).iter().map(any_value_json).collect())
+    } else if let Some(v) = map.get("kvlistValue") {
+        Value::Object(attributes_json(&v["values"]))
+    } else if let Some(v) = map.get("bytesValue") {
+        // Already base64 in OTLP/JSON, as the protobuf bytes are shown.
+        v.clone()
+    } else {
+        Value::Null
+    }
+}
+
+fn attributes(attributes: Vec<KeyValue>) -> Map<String, Value> {
+    attributes
+        .into_iter()
+        .map(|kv| (kv.key, kv.value.map(any_value).unwrap_or(Value::Null)))
+        .collect()
+}
+
+fn any_value(value: AnyValue) -> Value {
+    match value.value {
+        None => Value::Null,
+        Some(any_value::Value::StringValue(s)) => Value::from(s),
+        Some(any_value::Value::BoolValue(b)) => Value::from(b),
+        Some(any_value::Value::IntValue(i)) => Value::from(i),
+        Some(any_value::Value::DoubleValue(d)) => Value::from(d),
+        Some(any_value::Value::ArrayValue(a)) => {
+            Value::Array(a.values.into_iter().map(any_value).collect())
+        }
+        Some(any_value::Value::KvlistValue(kv)) => Value::Object(attributes(kv.values)),
+        Some(any_value::Value::BytesValue(b)) => Value::from(STANDARD.encode(b)),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ExportLogsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    resource_logs: Vec<ResourceLogs>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ResourceLogs {
+    #[prost(message, optional, tag = "1")]
+    resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    scope_logs: Vec<ScopeLogs>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ScopeLogs {
+    #[prost(message, optional, tag = "1")]
+    scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    log_records: Vec<LogRecord>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    version: String,
+    #[prost(message, repeated, tag = "3")]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct LogRecord {
+    #[prost(fixed64, tag = "1")]
+    time_unix_nano: u64,
+    #[prost(fixed64, tag = "11")]
+    observed_time_unix_nano: u64,
+    #[prost(int32, tag = "2")]
+    severity_number: i32,
+    #[prost(string, tag = "3")]
+    severity_text: String,
+    #[prost(message, optional, tag = "5")]
+    body: Option<AnyValue>,
+    #[prost(message, repeated, tag = "6")]
+    attributes: Vec<KeyValue>,
+    #[prost(bytes = "vec", tag = "9")]
+    trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "10")]
+    span_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct KeyValue {
+    #[prost(string, tag = "1")]
+    key: String,
+    #[prost(message, optional, tag = "2")]
+    value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnyValue {
+    #[prost(oneof = "any_value::Value", tags = "1, 2, 3, 4, 5, 6, 7")]
+    value: Option<any_value::Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ArrayValue {
+    #[prost(message, repeated, tag = "1")]
+    values: Vec<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct KeyValueList {
+    #[prost(message, repeated, tag = "1")]
+    values: Vec<KeyValue>,
+}
+
+mod any_value {
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(super) enum Value {
+        #[prost(string, tag = "1")]
+        StringValue(String),
+        #[prost(bool, tag = "2")]
+        BoolValue(bool),
+        #[prost(int64, tag = "3")]
+        IntValue(i64),
+        #[prost(double, tag = "4")]
+        DoubleValue(f64),
+        #[prost(message, tag = "5")]
+        ArrayValue(super::ArrayValue),
+        #[prost(message, tag = "6")]
+        KvlistValue(super::KeyValueList),
+        #[prost(bytes = "vec", tag = "7")]
+        BytesValue(Vec<u8>),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(value.to_string())),
+            }),
+        }
+    }
+
+    #[test]
+    fn flattens_attributes() {
+        let body = br#"{"resourceLogs":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"web"}},{"key":"host","value":{"stringValue":"a"}}]},
+            "scopeLogs":[{"scope":{"name":"lib","version":"1"},"logRecords":[{"timeUnixNano":"1700000000000000000","severityNumber":9,
+            "body":{"stringValue":"hi"},"attributes":[{"key":"host","value":{"stringValue":"b"}},{"key":"count","value":{"intValue":"3"}}]}]}]}]}"#;
+        let docs = logs_json(body).unwrap();
+        let doc = serde_json::from_str::<Value>(&docs[0]).unwrap();
+        assert_eq!(doc["service.name"], "web");
+        assert_eq!(doc["service"], "web");
+        assert_eq!(doc["host"], "b");
+        assert_eq!(doc["count"], 3);
+        assert_eq!(doc["scope.name"], "lib");
+        assert_eq!(doc["scope.version"], "1");
+        assert_eq!(doc["body"], "hi");
+        assert_eq!(doc["severity_number"], 9);
+        assert!(doc.get("resource").is_none());
+        assert!(doc.get("attributes").is_none());
+    }
+
+    #[test]
+    fn both_encodings_agree() {
+        let json = br#"{"resourceLogs":[{"scopeLogs":[{"logRecords":[{"body":{"stringValue":"hi"},
+            "attributes":[{"key":"k","value":{"stringValue":"v"}},{"key":"b","value":{"bytesValue":"AQI="}}],
+            "traceId":"0102","spanId":"03"}]}]}]}"#;
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: None,
+                scope_logs: vec![ScopeLogs {
+                    scope: None,
+                    log_records: vec![LogRecord {
+                        time_unix_nano: 0,
+                        observed_time_unix_nano: 0,
+                        severity_number: 0,
+                        severity_text: "".to_string(),
+                        body: Some(AnyValue {
+                            value: Some(any_value::Value::StringValue("hi".to_string())),
+                        }),
+                        attributes: vec![
+                            string("k", "v"),
+                            KeyValue {
+                                key: "b".to_string(),
+                                value: Some(AnyValue {
+                                    value: Some(any_value::Value::BytesValue(vec![1, 2])),
+                                }),
+                            },
+                        ],
+                        trace_id: vec![1, 2],
+                        span_id: vec![3],
+                    }],
+                }],
+            }],
+        };
+        assert_eq!(
+            logs_json(json).unwrap(),
+            logs_protobuf(&request.encode_to_vec()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+/// The slot a template has where its messages differ.
+pub const WILDCARD: &str = "<*>";
+
+/// Fields holding the message of a document, the whole line is used otherwise.
+const MESSAGE_POINTERS: [&str; 6] = [
+    "/message",
+    "/msg",
+    "/short_message",
+    "/body",
+    "/log",
+    "/_raw",
+];
+
+/// Share of tokens a message needs in common with a template to join it.
+const SIMILARITY: f64 = 0.5;
+
+/// Templates kept per message length and first token, the least seen template
+/// takes the message when full.
+const MAX_CLUSTERS: usize = 64;
+
+/// The window before the newest document anomalies are looked for in when no
+/// point in time is given.
+const WINDOW: i128 = 15 * 60 * 1_000_000_000;
+
+/// How many times faster a template has to come after the point in time than
+/// before to be flagged.
+const RATE_JUMP: f64 = 3.0;
+
+/// Documents a template needs after the point in time to be flagged for its
+/// rate.
+const MIN_COUNT: usize = 3;
+
+/// The first line of the message of a document.
+pub fn message(doc: &str) -> String {
+    let line = match serde_json::from_str::<Value>(doc) {
+        Ok(value @ Value::Object(_)) => MESSAGE_POINTERS
+            .iter()
+            .find_map(|p| value.pointer(p).and_then(|m| m.as_str()))
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| doc.to_string()),
+        _ => doc.to_string(),
+    };
+    line.lines().next().unwrap_or("").to_string()
+}
+
+/// Splits a message on whitespace, tokens with digits in them are numbers, ids
+/// and timestamps and always variable, they count as the same as the wildcard
+/// of a template.
+fn tokens(message: &str) -> Vec<String> {
+    message
+        .split_whitespace()
+        .map(|t| match t.chars().any(|c| c.is_ascii_digit()) {
+            true => WILDCARD.to_string(),
+            false => t.to_string(),
+        })
+        .collect()
+}
+
+struct Cluster {
+    tokens: Vec<String>,
+    count: usize,
+}
+
+impl Cluster {
+    fn similarity(&self, tokens: &[String]) -> f64 {
+        if tokens.is_empty() {
+            return 1.0;
+        }
+        let same = self
+            .tokens
+            .iter()
+            .zip(tokens)
+            .filter(|(t, m)| t == m)
+            .count();
+        same as f64 / tokens.len() as f64
+    }
+
+    fn merge(&mut self, tokens: Vec<String>) {
+        self.tokens
+            .iter_mut()
+            .zip(tokens)
+            .filter(|(t, m)| *t != m)
+            .for_each(|(t, _)| *t = WILDCARD.to_string());
+        self.count += 1;
+    }
+}
+
+/// Mines message templates the way Drain does, messages are grouped by their
+/// number of tokens and first token and join the most similar template there.
+#[derive(Default)]
+pub struct Drain {
+    groups: HashMap<(usize, String), Vec<usize>>,
+    clusters: Vec<Cluster>,
+}
+
+impl Drain {
+    /// Adds a document and returns the index of its template.
+    pub fn add(&mut self, doc: &str) -> usize {
+        let tokens = tokens(&message(doc));
+        let first = tokens.first().cloned().unwrap_or_default();
+        let group = self.groups.entry((tokens.len(), first)).or_default();
+        let best = group
+            .iter()
+            .map(|i| (*i, self.clusters[*i].similarity(&tokens)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        match best {
+            Some((i, similarity)) if similarity >= SIMILARITY => {
+                self.clusters[i].merge(tokens);
+                i
+            }
+            _ if group.len() >= MAX_CLUSTERS => {
+                let i = *group
+                    .iter()
+                    .min_by_key(|i| self.clusters[**i].count)
+                    .unwrap();
+                self.clusters[i].merge(tokens);
+                i
+            }
+            _ => {
+                group.push(self.clusters.len());
+                self.clusters.push(Cluster { tokens, count: 1 });
+                self.clusters.len() - 1
+            }
+        }
+    }
+
+    fn template(&self, i: usize) -> String {
+        self.clusters[i].tokens.join(" ")
+    }
+
+    /// The templates and how many documents each has, most seen first.
+    pub fn templates(self) -> Vec<(String, usize)> {
+        let mut templates = self
+            .clusters
+            .into_iter()
+            .map(|c| (c.tokens.join(" "), c.count))
+            .collect::<Vec<(String, usize)>>();
+        templates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        templates
+    }
+}
+
+/// A template searched for, documents match when their message has the same
+/// tokens apart from the wildcards.
+pub struct Template {
+    tokens: Vec<String>,
+}
+
+impl Template {
+    pub fn new(template: &str) -> Self {
+        Template {
+            tokens: template.split_whitespace().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn matches(&self, doc: &str) -> bool {
+        let tokens = tokens(&message(doc));
+        tokens.len() == self.tokens.len()
+            && self
+                .tokens
+                .iter()
+                .zip(tokens)
+                .all(|(t, m)| t.as_str() == WILDCARD || *t == m)
+    }
+
+    /// The words of the template to narrow the search with the index before
+    /// matching the documents.
+    pub fn keywords(&self) -> Vec<String> {
+        self.tokens
+            .iter()
+            .filter(|t| t.len() > 2 && t.chars().all(|c| c.is_alphanumeric() || c == '_'))
+            .map(|t| t.to_lowercase())
+            .collect()
+    }
+}
+
+pub enum Change {
+    /// The template was not seen before the point in time.
+    New,
+    /// The template comes this many times faster after the point in time.
+    Rate(f64),
+}
+
+pub struct Anomaly {
+    pub template: String,
+    pub change: Change,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Mines templates and counts each before and after a point in time, the
+/// baseline being everything mined before it.
+pub struct Timeline {
+    drain: Drain,
+    since: Option<i128>,
+    times: Vec<(usize, i128)>,
+}
+
+impl Timeline {
+    /// Without a point in time the last 15 minutes are compared to the rest.
+    pub fn new(since: Option<i128>) -> Self {
+        Timeline {
+            drain: Drain::default(),
+            since,
+            times: vec![],
+        }
+    }
+
+    /// Adds a document, it only counts towards anomalies when it has a time.
+    pub fn add(&mut self, doc: &str, time: Option<i128>) {
+        let i = self.drain.add(doc);
+        if let Some(time) = time {
+            self.times.push((i, time));
+        }
+    }
+
+    /// The point in time, none when no document had a time.
+    pub fn since(&self) -> Option<i128> {
+        self.since.or(self
+            .times
+            .iter()
+            .map(|(_, t)| *t)
+            .max()
+            .map(|newest| newest - WINDOW))
+    }
+
+    /// The oldest time mined, where the baseline starts.
+    pub fn oldest(&self) -> Option<i128> {
+        self.times.iter().map(|(_, t)| *t).min()
+    }
+
+    /// New templates first, most seen first, then those whose rate jumped the
+    /// most. Empty when no document came before the point in time.
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        let since = match self.since() {
+            None => return vec![],
+            Some(s) => s,
+        };
+        let (oldest, newest) = match (
+            self.times.iter().map(|(_, t)| *t).min(),
+            self.times.iter().map(|(_, t)| *t).max(),
+        ) {
+            (Some(o), Some(n)) if o < since => (o, n),
+            _ => return vec![],
+        };
+        let mut counts: HashMap<usize, (usize, usize)> = HashMap::new();
+        self.times.iter().for_each(|(i, t)| {
+            let count = counts.entry(*i).or_default();
+            match *t < since {
+                true => count.0 += 1,
+                false => count.1 += 1,
+            }
+        });
+        let span_before = (since - oldest).max(1_000_000_000) as f64;
+        let span_after = (newest - since).max(1_000_000_000) as f64;
+        let mut anomalies = counts
+            .into_iter()
+            .filter_map(|(i, (before, after))| {
+                let change = match before {
+                    _ if after == 0 => return None,
+                    0 => Change::New,
+                    _ => {
+                        let rate = (after as f64 / span_after) / (before as f64 / span_before);
+                        match after >= MIN_COUNT && rate >= RATE_JUMP {
+                            true => Change::Rate(rate),
+                            false => return None,
+                        }
+                    }
+                };
+                Some(Anomaly {
+                    template: self.drain.template(i),
+                    change,
+                    before,
+                    after,
+                })
+            })
+            .collect::<Vec<Anomaly>>();
+        anomalies.sort_by(|a, b| match (&a.change, &b.change) {
+            (Change::New, Change::New) => b.after.cmp(&a.after),
+            (Change::New, Change::Rate(_)) => std::cmp::Ordering::Less,
+            (Change::Rate(_), Change::New) => std::cmp::Ordering::Greater,
+            (Change::Rate(a), Change::Rate(b)) => b.partial_cmp(a).unwrap(),
+        });
+        anomalies
+    }
+
+    pub fn templates(self) -> Vec<(String, usize)> {
+        self.drain.templates()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A word without digits for each number, digits would make it a wildcard.
+    fn word(i: usize) -> String {
+        format!(
+            "{}{}",
+            (b'a' + (i % 26) as u8) as char,
+            (b'a' + (i / 26 % 26) as u8) as char
+        )
+    }
+
+    #[test]
+    fn takes_the_message_of_a_document() {
+        assert_eq!(message(r#"{"msg":"started\nat"}"#), "started");
+        assert_eq!(message(r#"{"a":1}"#), r#"{"a":1}"#);
+        assert_eq!(message("plain line"), "plain line");
+    }
+
+    #[test]
+    fn clusters_similar_messages() {
+        let mut drain = Drain::default();
+        let a = drain.add("user alice logged in from home");
+        let b = drain.add("user bob logged in from work");
+        let c = drain.add("user carol logged in at night");
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+        assert_eq!(
+            drain.templates(),
+            vec![("user <*> logged in <*> <*>".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn splits_dissimilar_messages() {
+        let mut drain = Drain::default();
+        let a = drain.add("user alice logged in");
+        let b = drain.add("user cache was cleared");
+        let c = drain.add("disk alice logged in");
+        let d = drain.add("user alice logged in twice");
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+        assert_eq!(drain.templates().len(), 4);
+    }
+
+    #[test]
+    fn caps_the_templates_of_a_group() {
+        let mut drain = Drain::default();
+        for i in 0..MAX_CLUSTERS {
+            drain.add(&format!(
+                "job {} {} {}",
+                word(i),
+                word(i + 100),
+                word(i + 200)
+            ));
+        }
+        drain.add("job aa bb cc");
+        drain.add("job aa bb cc");
+        let i = drain.add("job zz yy xx");
+        assert_eq!(drain.groups[&(4, "job".to_string())].len(), MAX_CLUSTERS);
+        assert_eq!(drain.template(i), "job <*> <*> <*>");
+    }
+
+    #[test]
+    fn matches_templates_on_the_wildcards() {
+        let template = Template::new("user <*> logged in from <*>");
+        assert!(template.matches(r#"{"message":"user alice logged in from home"}"#));
+        assert!(template.matches("user 42 logged in from 10.0.0.1"));
+        assert!(!template.matches("user alice logged out from home"));
+        assert!(!template.matches("user alice logged in"));
+        assert!(!Template::new("a 1").matches("a b"));
+        assert!(Template::new("").is_empty());
+    }
+
+    #[test]
+    fn searches_the_words_of_a_template() {
+        let template = Template::new("GET <*> in db_query of Order-42");
+        assert_eq!(template.keywords(), vec!["get", "db_query"]);
+    }
+
+    fn minute(m: i128) -> Option<i128> {
+        Some(m * 60 * 1_000_000_000)
+    }
+
+    #[test]
+    fn flags_new_and_faster_templates() {
+        let mut timeline = Timeline::new(None);
+        (0..60).for_each(|m| timeline.add("cache hit for key", minute(m)));
+        (60..75).for_each(|m| timeline.add("cache hit for key", minute(m)));
+        timeline.add("payment failed for order", minute(10));
+        (61..64).for_each(|m| timeline.add("payment failed for order", minute(m)));
+        timeline.add("disk nearly full now", minute(20));
+        (61..63).for_each(|m| timeline.add("disk nearly full now", minute(m)));
+        (70..72).for_each(|m| timeline.add("worker crashed again", minute(m)));
+        timeline.add("shutdown requested by admin", minute(75));
+        timeline.add("restart without a time", None);
+        assert_eq!(timeline.since(), minute(60));
+        assert_eq!(timeline.oldest(), minute(0));
+
+        let anomalies = timeline.anomalies();
+        let flagged = anomalies
+            .iter()
+            .map(|a| (a.template.as_str(), a.before, a.after))
+            .collect::<Vec<(&str, usize, usize)>>();
+        assert_eq!(
+            flagged,
+            vec![
+                ("worker crashed again", 0, 2),
+                ("shutdown requested by admin", 0, 1),
+                ("payment failed for order", 1, 3),
+            ]
+        );
+        assert!(matches!(anomalies[0].change, Change::New));
+        assert!(matches!(anomalies[2].change, Change::Rate(r) if (r - 12.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn needs_documents_before_the_point_in_time() {
+        let mut timeline = Timeline::new(minute(5));
+        (5..10).for_each(|m| timeline.add("worker crashed again", minute(m)));
+        assert!(timeline.anomalies().is_empty());
+        assert!(Timeline::new(None).anomalies().is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{ListParams, LogParams};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::{Api, Client, Config};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::data::KubeContext;
+use crate::index::CommandMessage;
+use crate::ingest::keep;
+use crate::sources::{record_line, remove, set_state, StreamState};
+
+/// Lists the contexts in the kubeconfig, with the current context checked.
+pub fn kube_contexts() -> Vec<KubeContext> {
+    let config = match Kubeconfig::read() {
+        Ok(c) => c,
+        Err(_) => return vec![],
+    };
+    let current = config.current_context.unwrap_or_default();
+    config
+        .contexts
+        .iter()
+        .map(|c| KubeContext {
+            name: c.name.to_string(),
+            checked: c.name == current,
+        })
+        .collect()
+}
+
+async fn client(context: &str) -> Result<(Client, String), String> {
+    if context.is_empty() {
+        let config = Config::infer().await.map_err(|e| e.to_string())?;
+        let cluster = config.cluster_url.host().unwrap_or_default().to_string();
+        let client = Client::try_from(config).map_err(|e| e.to_string())?;
+        return Ok((client, cluster));
+    }
+    let options = KubeConfigOptions {
+        context: Some(context.to_string()),
+        ..KubeConfigOptions::default()
+    };
+    let config = Config::from_kubeconfig(&options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let cluster = config.cluster_url.host().unwrap_or_default().to_string();
+    let client = Client::try_from(config).map_err(|e| e.to_string())?;
+    Ok((client, cluster))
+}
+
+/// The pod log streams followed so far, one per pod container, keyed by
+/// `pod:<context>/<pod>/<container>`.
+pub struct PodStreams {
+    tx: Sender<CommandMessage>,
+    clients: HashMap<String, (Api<Pod>, String)>,
+    streams: HashMap<String, (PodStream, JoinHandle<()>)>,
+}
+
+#[derive(Clone)]
+struct PodStream {
+    context: String,
+    pod: String,
+    container: String,
+}
+
+impl PodStream {
+    fn key(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            context_key(&self.context),
+            self.pod,
+            self.container
+        )
+    }
+}
+
+/// Status key for a context, an empty context name is the inferred default.
+fn context_key(context: &str) -> String {
+    match context.is_empty() {
+        true => "pod:(default)".to_string(),
+        false => format!("pod:{}", context),
+    }
+}
+
+impl PodStreams {
+    pub fn new(tx: Sender<CommandMessage>) -> Self {
+        PodStreams {
+            tx,
+            clients: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Follows the logs of every pod container in the default namespace of each
+    /// context not attached yet. An empty context name means the inferred default
+    /// configuration.
+    pub async fn attach(&mut self, contexts: Vec<String>) {
+        for context in contexts {
+            if self.clients.contains_key(&context) {
+                continue;
+            }
+            self.attach_context(context).await;
+        }
+    }
+
+    async fn attach_context(&mut self, context: String) {
+        let key = context_key(&context);
+        set_state(&key, StreamState::Connecting);
+        let (client, cluster) = match client(&context).await {
+            Ok(c) => c,
+            Err(e) => {
+                set_state(&key, StreamState::Error(e));
+                return;
+            }
+        };
+        let pods: Api<Pod> = Api::default_namespaced(client);
+        let x = match pods.list(&ListParams::default()).await {
+            Ok(c) => c,
+            Err(e) => {
+                set_state(&key, StreamState::Error(e.to_string()));
+                return;
+            }
+        };
+        remove(&key);
+        self.clients
+            .insert(context.to_string(), (pods, cluster.to_string()));
+        for p in x.items {
+            let name: String = match p.metadata.name {
+                None => {
+                    continue;
+                }
+                Some(s) => s,
+            };
+            let containers = p
+                .spec
+                .map(|s| {
+                    s.containers
+                        .into_iter()
+                        .map(|c| c.name)
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or(vec![]);
+            for container in containers {
+                self.start(PodStream {
+                    context: context.to_string(),
+                    pod: name.to_string(),
+                    container,
+                });
+            }
+        }
+    }
+
+    fn start(&mut self, stream: PodStream) {
+        let (pods, cluster) = match self.clients.get(&stream.context) {
+            None => return,
+            Some(c) => c.clone(),
+        };
+        let key = stream.key();
+        set_state(&key, StreamState::Connecting);
+        let handle = tokio::spawn(follow(pods, cluster, stream.clone(), self.tx.clone()));
+        if let Some((_, old)) = self.streams.insert(key, (stream, handle)) {
+            old.abort();
+        }
+    }
+
+    pub fn stop(&mut self, key: &str) {
+        if let Some((_, handle)) = self.streams.get(key) {
+            handle.abort();
+            set_state(key, StreamState::Stopped);
+        }
+    }
+
+    /// Restarts a stream, or retries attaching a context that failed.
+    pub async fn restart(&mut self, key: &str) {
+        match self.streams.get(key) {
+            Some((stream, _)) => {
+                let stream = stream.clone();
+                self.start(stream);
+            }
+            None => {
+                let context = match key {
+                    "pod:(default)" => "".to_string(),
+                    _ => key.strip_prefix("pod:").unwrap_or(key).to_string(),
+                };
+                self.clients.remove(&context);
+                self.attach_context(context).await;
+            }
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        self.streams.values().for_each(|(_, h)| h.abort());
+    }
+}
+
+/// Reconnects after a failed stream this many times before giving up.
+const RETRIES: u32 = 5;
+
+/// Waits 5s after the first failure, doubling up to a minute.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs((5u64 << attempt.saturating_sub(1).min(4)).min(60))
+}
+
+async fn follow(
+    pods: Api<Pod>,
+    cluster: String,
+    stream: PodStream,
+    sender: Sender<CommandMessage>,
+) {
+    let key = stream.key();
+    let mut attempt = 0;
+    let mut last_line: Option<Instant> = None;
+    loop {
+        if attempt > 0 {
+            let delay = backoff(attempt);
+            set_state(&key, StreamState::Retrying(Instant::now() + delay));
+            sleep(delay).await;
+            set_state(&key, StreamState::Connecting);
+        }
+        // Reconnecting picks up from the last line read, a line from the same
+        // second can come twice
+        let since_seconds = last_line.map(|l| l.elapsed().as_secs() as i64 + 1);
+        let logs = pods
+            .log_stream(
+                &stream.pod,
+                &LogParams {
+                    follow: true,
+                    container: Some(stream.container.to_string()),
+                    since_seconds,
+                    ..LogParams::default()
+                },
+            )
+            .await;
+        let before = last_line;
+        let error = match logs {
+            Ok(logs) => match read(
+                Box::pin(logs),
+                &key,
+                &stream,
+                &cluster,
+                &sender,
+                &mut last_line,
+            )
+            .await
+            {
+                Ok(()) => return,
+                Err(e) => e,
+            },
+            Err(e) => e.to_string(),
+        };
+        // Count again from the start when the stream delivered lines before failing
+        if last_line != before {
+            attempt = 0;
+        }
+        attempt += 1;
+        if attempt > RETRIES {
+            set_state(&key, StreamState::Error(error));
+            return;
+        }
+    }
+}
+
+/// Sends the lines of the stream until it ends, the error when it fails.
+async fn read(
+    mut logs: impl Stream<Item = Result<impl AsRef<[u8]>, impl Display>> + Unpin,
+    key: &str,
+    stream: &PodStream,
+    cluster: &str,
+    sender: &Sender<CommandMessage>,
+    last_line: &mut Option<Instant>,
+) -> Result<(), String> {
+    set_state(key, StreamState::Streaming);
+    let mut buff = String::new();
+    loop {
+        let item = match logs.try_next().await {
+            Ok(Some(s)) => s,
+            Ok(None) => {
+                set_state(key, StreamState::Ended);
+                return Ok(());
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let s = String::from_utf8_lossy(item.as_ref()).to_string();
+        buff.push_str(&s);
+        if !s.ends_with("\n") {
+            continue;
+        }
+        let lines = buff.clone();
+        buff = String::new();
+        // A chunk can hold several lines, a stack trace is joined again on ingest.
+        let messages = lines
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter(|l| record_line(key, l.len()) && keep(key, l))
+            .map(|line| {
+                let tags = vec![
+                    ("pod".to_string(), stream.pod.to_string()),
+                    ("container".to_string(), stream.container.to_string()),
+                    ("context".to_string(), stream.context.to_string()),
+                    ("cluster".to_string(), cluster.to_string()),
+                ];
+                CommandMessage::InsertJson(key.to_string(), line.trim_end().to_string(), tags)
+            })
+            .collect::<Vec<CommandMessage>>();
+        if messages.is_empty() {
+            continue;
+        }
+        *last_line = Some(Instant::now());
+        // Off the async workers as the channel blocks until the index thread
+        // takes the message, which may itself be waiting on a pod request
+        let sender = sender.clone();
+        let sent = tokio::task::spawn_blocking(move || {
+            messages.into_iter().all(|m| sender.send(m).is_ok())
+        })
+        .await
+        .unwrap_or(false);
+        if !sent {
+            return Ok(());
+        }
+    }
+}
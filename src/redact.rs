@@ -0,0 +1,311 @@
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::ingest::RAW;
+
+const MASK: &str = "[REDACTED]";
+/// Fields that may repeat a pointer's value as part of the line.
+const TEXT_FIELDS: [&str; 3] = [RAW, "message", "msg"];
+/// Shorter pointer values are only redacted at the pointer, not in the text
+/// fields, where they would match unrelated words.
+const MIN_TOKEN: usize = 4;
+
+/// Masks or hashes values before a document is indexed or written to disk.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactRule {
+    pub name: String,
+    /// A built-in detector, `bearer`, `jwt`, `email`, `credit_card` or `ip`.
+    pub detector: String,
+    /// A regex, the `value` capture is redacted when there is one and the whole
+    /// match otherwise.
+    pub pattern: String,
+    /// A JSON pointer, `/user/email`, whose value is redacted there and where it
+    /// occurs as a whole word in `_raw`, `message` or `msg`.
+    pub pointer: String,
+    pub action: RedactAction,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactAction {
+    #[default]
+    Mask,
+    /// A short SHA-256 so equal values can still be searched and correlated.
+    Hash,
+}
+
+pub struct Redactor {
+    rules: Vec<Compiled>,
+}
+
+struct Compiled {
+    action: RedactAction,
+    target: Target,
+}
+
+/// Checks a value a detector matched, the Luhn sum of a card number.
+type Check = fn(&str) -> bool;
+
+enum Target {
+    Text(Regex, Check),
+    Pointer(String),
+}
+
+impl Redactor {
+    pub fn new(rules: &[RedactRule]) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(|r| {
+                let target = match (r.detector.as_str(), r.pattern.as_str(), r.pointer.as_str()) {
+                    (d, "", "") if !d.is_empty() => {
+                        let (pattern, valid) = detector(d)
+                            .ok_or(format!("redact {}: unknown detector {}", r.name, d))?;
+                        Target::Text(Regex::new(pattern).unwrap(), valid)
+                    }
+                    ("", p, "") if !p.is_empty() => Target::Text(
+                        Regex::new(p).map_err(|e| format!("redact {}: {}", r.name, e))?,
+                        |_| true,
+                    ),
+                    ("", "", p) if p.starts_with('/') => Target::Pointer(p.to_string()),
+                    _ => {
+                        return Err(format!(
+                        "redact {}: needs one of detector, pattern or a pointer starting with /",
+                        r.name
+                    ))
+                    }
+                };
+                Ok(Compiled {
+                    action: r.action,
+                    target,
+                })
+            })
+            .collect::<Result<Vec<Compiled>, String>>()?;
+        Ok(Redactor { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Redacts a JSON document, or the text of a line that is not JSON.
+    pub fn redact(&self, doc: &str) -> String {
+        let mut value = match serde_json::from_str::<Value>(doc) {
+            Ok(v @ Value::Object(_)) => v,
+            _ => return self.redact_text(doc),
+        };
+        let mut replaced = vec![];
+        for rule in &self.rules {
+            if let Target::Pointer(pointer) = &rule.target {
+                if let Some(v) = value.pointer_mut(pointer) {
+                    let original = match &*v {
+                        Value::String(s) => s.to_string(),
+                        Value::Null => continue,
+                        other => other.to_string(),
+                    };
+                    let replacement = apply(rule.action, &original);
+                    *v = Value::from(replacement.to_string());
+                    replaced.push((original, replacement));
+                }
+            }
+        }
+        self.redact_value(&mut value);
+        for field in TEXT_FIELDS {
+            if let Some(Value::String(text)) = value.get_mut(field) {
+                for (original, replacement) in &replaced {
+                    if original.chars().count() >= MIN_TOKEN {
+                        *text = replace_tokens(text, original, replacement);
+                    }
+                }
+            }
+        }
+        value.to_string()
+    }
+
+    /// Every string in the document, `_raw` holding the original line included.
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.redact_text(s),
+            Value::Array(a) => a.iter_mut().for_each(|v| self.redact_value(v)),
+            Value::Object(m) => m.values_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+
+    fn redact_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            if let Target::Text(regex, valid) = &rule.target {
+                text = regex
+                    .replace_all(&text, |c: &Captures| {
+                        let whole = c.get(0).unwrap();
+                        let value = c.name("value").unwrap_or(whole);
+                        if !valid(value.as_str()) {
+                            return whole.as_str().to_string();
+                        }
+                        format!(
+                            "{}{}{}",
+                            &whole.as_str()[..value.start() - whole.start()],
+                            apply(rule.action, value.as_str()),
+                            &whole.as_str()[value.end() - whole.start()..]
+                        )
+                    })
+                    .to_string();
+            }
+        }
+        text
+    }
+}
+
+fn apply(action: RedactAction, value: &str) -> String {
+    match action {
+        RedactAction::Mask => MASK.to_string(),
+        RedactAction::Hash => {
+            let digest = Sha256::digest(value.as_bytes());
+            let hex = digest[..8]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            format!("sha256:{}", hex)
+        }
+    }
+}
+
+/// Replaces the occurrences of `token` that are not part of a longer word.
+fn replace_tokens(text: &str, token: &str, replacement: &str) -> String {
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut out = String::new();
+    let mut last = 0;
+    for (start, _) in text.match_indices(token) {
+        let end = start + token.len();
+        if word(text[..start].chars().next_back()) || word(text[end..].chars().next()) {
+            continue;
+        }
+        out.push_str(&text[last..start]);
+        out.push_str(replacement);
+        last = end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// The built-in detectors, a regex and a check on the matched value.
+fn detector(name: &str) -> Option<(&'static str, Check)> {
+    match name {
+        "bearer" => Some((r"(?i)\bbearer\s+(?P<value>[A-Za-z0-9\-._~+/]+=*)", |_| true)),
+        "jwt" => Some((
+            r"\beyJ[A-Za-z0-9_-]+\.eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]*",
+            |_| true,
+        )),
+        "email" => Some((
+            r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b",
+            |_| true,
+        )),
+        "credit_card" => Some((r"\b(?:\d[ -]?){12,18}\d\b", luhn)),
+        "ip" => Some((
+            r"\b(?:\d{1,3}\.){3}\d{1,3}\b|\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b|\b(?:[0-9A-Fa-f]{1,4}:){1,7}:(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6})?\b",
+            valid_ip,
+        )),
+        _ => None,
+    }
+}
+
+/// Card numbers pass the Luhn check, which keeps ids and timestamps out.
+fn luhn(value: &str) -> bool {
+    let digits = value
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .map(|c| c.to_digit(10).unwrap())
+        .collect::<Vec<u32>>();
+    if digits.len() < 13 || digits.len() > 19 {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match i % 2 {
+            1 if d * 2 > 9 => d * 2 - 9,
+            1 => d * 2,
+            _ => *d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn valid_ip(value: &str) -> bool {
+    value.parse::<std::net::IpAddr>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redactor(rule: RedactRule) -> Redactor {
+        Redactor::new(&[rule]).unwrap()
+    }
+
+    #[test]
+    fn checks_card_numbers() {
+        assert!(luhn("4111 1111 1111 1111"));
+        assert!(luhn("5500-0000-0000-0004"));
+        assert!(!luhn("4111 1111 1111 1112"));
+        assert!(!luhn("1700000000123"));
+        assert!(!luhn("123456789012"));
+
+        let cards = redactor(RedactRule {
+            detector: "credit_card".to_string(),
+            ..RedactRule::default()
+        });
+        assert_eq!(
+            cards.redact("paid with 4111111111111111 at 1700000000123"),
+            "paid with [REDACTED] at 1700000000123"
+        );
+    }
+
+    #[test]
+    fn checks_ips() {
+        let ips = redactor(RedactRule {
+            detector: "ip".to_string(),
+            ..RedactRule::default()
+        });
+        assert_eq!(
+            ips.redact("from 10.0.0.1 and fe80::1 not 999.1.1.1"),
+            "from [REDACTED] and [REDACTED] not 999.1.1.1"
+        );
+        assert_eq!(ips.redact("version 1.2.3"), "version 1.2.3");
+    }
+
+    #[test]
+    fn redacts_at_the_pointer_and_whole_words_of_the_line() {
+        let users = redactor(RedactRule {
+            pointer: "/user".to_string(),
+            ..RedactRule::default()
+        });
+        let doc = json!({"user": "anna", "team": "hannah", "message": "anna logged in", "_raw": "user=anna annab"});
+        let redacted = serde_json::from_str::<Value>(&users.redact(&doc.to_string())).unwrap();
+        assert_eq!(
+            redacted,
+            json!({"user": "[REDACTED]", "team": "hannah", "message": "[REDACTED] logged in", "_raw": "user=[REDACTED] annab"})
+        );
+
+        let short = json!({"user": "al", "message": "al walked"});
+        let redacted = serde_json::from_str::<Value>(&users.redact(&short.to_string())).unwrap();
+        assert_eq!(
+            redacted,
+            json!({"user": "[REDACTED]", "message": "al walked"})
+        );
+    }
+
+    #[test]
+    fn replaces_whole_tokens() {
+        assert_eq!(
+            replace_tokens("a.b a.bc xa.b a.b", "a.b", "X"),
+            "X a.bc xa.b X"
+        );
+        assert_eq!(replace_tokens("aaaa", "aa", "X"), "aaaa");
+    }
+}
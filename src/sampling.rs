@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+
+use crate::level::{level, Level};
+use crate::sources::record_dropped;
+
+/// Limits how many documents a chatty source adds. Every source matching the
+/// glob gets its own counter and token bucket, a pod's container or a file.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingRule {
+    pub name: String,
+    pub source: String,
+    /// Keeps the first of every N documents.
+    pub keep_one_in: u64,
+    /// Token bucket rate, 0 for no limit.
+    pub lines_per_second: f64,
+    /// Token bucket size, the rate when 0.
+    pub burst: f64,
+    /// Errors are kept even when sampled out or over the rate.
+    pub keep_errors: bool,
+}
+
+impl Default for SamplingRule {
+    fn default() -> Self {
+        SamplingRule {
+            name: "".to_string(),
+            source: "*".to_string(),
+            keep_one_in: 1,
+            lines_per_second: 0.0,
+            burst: 0.0,
+            keep_errors: true,
+        }
+    }
+}
+
+pub struct Sampler {
+    rules: Vec<(Pattern, SamplingRule)>,
+    state: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    seen: u64,
+    tokens: f64,
+    refilled: Instant,
+    /// Whether the last event was kept, for the lines continuing it.
+    kept: bool,
+}
+
+impl Sampler {
+    pub fn new(rules: &[SamplingRule]) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(|r| {
+                Pattern::new(&r.source)
+                    .map(|p| (p, r.clone()))
+                    .map_err(|e| format!("sampling {}: {}", r.name, e))
+            })
+            .collect::<Result<Vec<(Pattern, SamplingRule)>, String>>()?;
+        Ok(Sampler {
+            rules,
+            state: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether to index the document, counting it as dropped for the source
+    /// otherwise. A line continuing a multiline event is kept when the event's
+    /// first line was.
+    pub fn keep(&self, source: &str, doc: &str, continues: bool) -> bool {
+        let rule = match self.rules.iter().find(|(p, _)| p.matches(source)) {
+            None => return true,
+            Some((_, r)) => r,
+        };
+        if continues {
+            let kept = self
+                .state
+                .lock()
+                .unwrap()
+                .get(source)
+                .is_none_or(|b| b.kept);
+            if !kept {
+                record_dropped(source);
+            }
+            return kept;
+        }
+        let burst = match rule.burst > 0.0 {
+            true => rule.burst,
+            false => rule.lines_per_second.max(1.0),
+        };
+        let mut state = self.state.lock().unwrap();
+        let bucket = state.entry(source.to_string()).or_insert(Bucket {
+            seen: 0,
+            tokens: burst,
+            refilled: Instant::now(),
+            kept: true,
+        });
+        bucket.seen += 1;
+        let mut keep = (bucket.seen - 1).is_multiple_of(rule.keep_one_in.max(1));
+        if keep && rule.lines_per_second > 0.0 {
+            let elapsed = bucket.refilled.elapsed().as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rule.lines_per_second).min(burst);
+            bucket.refilled = Instant::now();
+            keep = bucket.tokens >= 1.0;
+            if keep {
+                bucket.tokens -= 1.0;
+            }
+        }
+        let keep = keep || rule.keep_errors && is_error(doc);
+        bucket.kept = keep;
+        drop(state);
+        if !keep {
+            record_dropped(source);
+        }
+        keep
+    }
+}
+
+/// Looks at the usual level fields, syslog severities and for lines that are
+/// not JSON the text itself.
+fn is_error(doc: &str) -> bool {
+    level(doc, "") == Some(Level::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampler(rule: SamplingRule) -> Sampler {
+        Sampler::new(&[rule]).unwrap()
+    }
+
+    #[test]
+    fn keeps_one_in_n() {
+        let sampler = sampler(SamplingRule {
+            source: "pod:*".to_string(),
+            keep_one_in: 3,
+            ..SamplingRule::default()
+        });
+        let kept = (0..9)
+            .filter(|_| sampler.keep("pod:a", "line", false))
+            .count();
+        assert_eq!(kept, 3);
+        assert!((0..9).all(|_| sampler.keep("file:a", "line", false)));
+    }
+
+    #[test]
+    fn limits_the_rate_and_keeps_errors() {
+        let sampler = sampler(SamplingRule {
+            lines_per_second: 2.0,
+            ..SamplingRule::default()
+        });
+        assert!(sampler.keep("stdin", "a", false));
+        assert!(sampler.keep("stdin", "b", false));
+        assert!(!sampler.keep("stdin", "c", false));
+        assert!(sampler.keep("stdin", r#"{"level":"error"}"#, false));
+    }
+
+    #[test]
+    fn continuation_lines_follow_their_event() {
+        let sampler = sampler(SamplingRule {
+            keep_one_in: 2,
+            keep_errors: false,
+            ..SamplingRule::default()
+        });
+        assert!(sampler.keep("stdin", "Exception", false));
+        assert!(sampler.keep("stdin", "  at a", true));
+        assert!(!sampler.keep("stdin", "Exception", false));
+        assert!(!sampler.keep("stdin", "  at a", true));
+    }
+}
@@ -0,0 +1,187 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A Rhai script run on every document after the transforms. The document is
+/// `doc`, a map, or a string for lines that did not parse, and the source's
+/// status key is `source`. Changes to `doc` are kept, returning `false` drops
+/// the document and returning an array of maps splits it, any other value
+/// returned is ignored.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptConfig {
+    pub source: String,
+    /// Wall time allowed per document.
+    pub timeout_ms: u64,
+    pub max_operations: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        ScriptConfig {
+            source: "".to_string(),
+            timeout_ms: 20,
+            max_operations: 1_000_000,
+        }
+    }
+}
+
+/// The field a document keeps the error in when the script fails on it.
+const SCRIPT_ERROR: &str = "_script_error";
+
+thread_local! {
+    static STARTED: Cell<Instant> = Cell::new(Instant::now());
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: Option<AST>,
+}
+
+impl Script {
+    pub fn new(config: &ScriptConfig) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        // Scripts only see the document, nothing of the file system or network
+        // is exposed by the engine.
+        engine.disable_symbol("eval");
+        engine.set_max_operations(config.max_operations);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(1 << 20);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+        let timeout = Duration::from_millis(config.timeout_ms);
+        engine.on_progress(
+            move |_| match STARTED.with(|s| s.get()).elapsed() > timeout {
+                true => Some(Dynamic::from("timed out")),
+                false => None,
+            },
+        );
+        engine.register_fn("base64_decode", |s: &str| -> Dynamic {
+            match STANDARD.decode(s.trim()) {
+                Ok(b) => Dynamic::from(String::from_utf8_lossy(&b).to_string()),
+                Err(_) => Dynamic::UNIT,
+            }
+        });
+        engine.register_fn("parse_json", |s: &str| -> Dynamic {
+            serde_json::from_str::<Value>(s)
+                .ok()
+                .and_then(|v| to_dynamic(v).ok())
+                .unwrap_or(Dynamic::UNIT)
+        });
+        let ast = match config.source.trim().is_empty() {
+            true => None,
+            false => Some(
+                engine
+                    .compile(&config.source)
+                    .map_err(|e| format!("script: {}", e))?,
+            ),
+        };
+        Ok(Script { engine, ast })
+    }
+
+    /// Returns the documents the script turned the document into.
+    pub fn run(&self, source: &str, doc: String) -> Vec<String> {
+        let ast = match &self.ast {
+            None => return vec![doc],
+            Some(a) => a,
+        };
+        let value = serde_json::from_str::<Value>(&doc)
+            .ok()
+            .filter(|v| v.is_object())
+            .and_then(|v| to_dynamic(v).ok());
+        let is_object = value.is_some();
+        let mut scope = Scope::new();
+        scope.push("source", source.to_string());
+        scope.push("doc", value.unwrap_or(Dynamic::from(doc.to_string())));
+        STARTED.with(|s| s.set(Instant::now()));
+        let result = match self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, ast) {
+            Ok(r) => r,
+            Err(e) => return vec![with_error(doc, is_object, &e.to_string())],
+        };
+        if result.as_bool() == Ok(false) {
+            return vec![];
+        }
+        if result.is_array() {
+            return result
+                .into_array()
+                .unwrap()
+                .into_iter()
+                .filter_map(to_doc)
+                .collect();
+        }
+        let changed = scope.get_value::<Dynamic>("doc").unwrap_or(Dynamic::UNIT);
+        match to_doc(changed) {
+            Some(changed) => vec![changed],
+            None => vec![with_error(doc, is_object, "doc is not a map or a string")],
+        }
+    }
+}
+
+fn to_doc(value: Dynamic) -> Option<String> {
+    if value.is_string() {
+        return value.into_string().ok();
+    }
+    match value.is_map() {
+        true => from_dynamic::<Value>(&value).ok().map(|v| v.to_string()),
+        false => None,
+    }
+}
+
+fn with_error(doc: String, is_object: bool, error: &str) -> String {
+    if !is_object {
+        return doc;
+    }
+    let mut value = serde_json::from_str::<Value>(&doc).unwrap();
+    value[SCRIPT_ERROR] = Value::from(error);
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str, doc: &str) -> Vec<String> {
+        let script = Script::new(&ScriptConfig {
+            source: source.to_string(),
+            ..ScriptConfig::default()
+        })
+        .unwrap();
+        script.run("stdin", doc.to_string())
+    }
+
+    #[test]
+    fn keeps_changes_to_doc() {
+        assert_eq!(run("doc.b = 2", r#"{"a":1}"#), vec![r#"{"a":1,"b":2}"#]);
+        assert_eq!(run("doc.b = 2; 42", r#"{"a":1}"#), vec![r#"{"a":1,"b":2}"#]);
+        assert_eq!(run(r#""text""#, r#"{"a":1}"#), vec![r#"{"a":1}"#]);
+        assert_eq!(run("doc += \"!\"", "plain"), vec!["plain!"]);
+    }
+
+    #[test]
+    fn drops_and_splits() {
+        assert!(run("false", r#"{"a":1}"#).is_empty());
+        assert_eq!(
+            run("[#{n: 1}, #{n: 2}]", r#"{"a":1}"#),
+            vec![r#"{"n":1}"#, r#"{"n":2}"#]
+        );
+    }
+
+    #[test]
+    fn marks_errors() {
+        let docs = run("doc = 5", r#"{"a":1}"#);
+        let doc = serde_json::from_str::<Value>(&docs[0]).unwrap();
+        assert_eq!(doc["a"], 1);
+        assert!(doc[SCRIPT_ERROR].is_string());
+
+        let docs = run("throw \"bad\"", r#"{"a":1}"#);
+        assert!(docs[0].contains(SCRIPT_ERROR));
+        assert_eq!(run("throw \"bad\"", "plain"), vec!["plain"]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use druid::im::Vector;
+use human_bytes::human_bytes;
+use num_format::{Locale, ToFormattedString};
+use once_cell::sync::Lazy;
+
+use crate::data::StreamStatus;
+
+/// Status of every stream feeding the store, keyed by stream name.
+pub static SOURCES: Lazy<Mutex<BTreeMap<String, SourceStats>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Clone)]
+pub enum StreamState {
+    Connecting,
+    /// Waiting until the instant before connecting again.
+    Retrying(Instant),
+    Streaming,
+    Ended,
+    Stopped,
+    Error(String),
+}
+
+#[derive(Clone)]
+pub struct SourceStats {
+    pub state: StreamState,
+    pub lines: u64,
+    pub bytes: u64,
+    /// Documents left out by sampling and rate limits.
+    pub dropped: u64,
+    pub last_line: Option<Instant>,
+}
+
+impl Default for SourceStats {
+    fn default() -> Self {
+        SourceStats {
+            state: StreamState::Connecting,
+            lines: 0,
+            bytes: 0,
+            dropped: 0,
+            last_line: None,
+        }
+    }
+}
+
+pub fn set_state(key: &str, state: StreamState) {
+    SOURCES
+        .lock()
+        .unwrap()
+        .entry(key.to_string())
+        .or_default()
+        .state = state;
+}
+
+/// Counts a line read from the stream, false when the stream was stopped and
+/// the line is to be left out.
+pub fn record_line(key: &str, bytes: usize) -> bool {
+    let mut sources = SOURCES.lock().unwrap();
+    let stats = sources.entry(key.to_string()).or_default();
+    if let StreamState::Stopped = stats.state {
+        return false;
+    }
+    stats.state = StreamState::Streaming;
+    stats.lines += 1;
+    stats.bytes += bytes as u64;
+    stats.last_line = Some(Instant::now());
+    true
+}
+
+pub fn record_dropped(key: &str) {
+    SOURCES
+        .lock()
+        .unwrap()
+        .entry(key.to_string())
+        .or_default()
+        .dropped += 1;
+}
+
+pub fn dropped() -> u64 {
+    SOURCES.lock().unwrap().values().map(|s| s.dropped).sum()
+}
+
+pub fn remove(key: &str) {
+    SOURCES.lock().unwrap().remove(key);
+}
+
+pub fn snapshot() -> Vector<StreamStatus> {
+    SOURCES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, stats)| StreamStatus {
+            key: key.to_string(),
+            state: match &stats.state {
+                StreamState::Connecting => "connecting".to_string(),
+                StreamState::Retrying(at) => format!(
+                    "retrying in {}s",
+                    at.saturating_duration_since(Instant::now()).as_secs()
+                ),
+                StreamState::Streaming => "streaming".to_string(),
+                StreamState::Ended => "ended".to_string(),
+                StreamState::Stopped => "stopped".to_string(),
+                StreamState::Error(e) => format!("error: {}", e),
+            },
+            lines: stats.lines.to_formatted_string(&Locale::en),
+            bytes: human_bytes(stats.bytes as f64),
+            dropped: stats.dropped.to_formatted_string(&Locale::en),
+            last_line: match stats.last_line {
+                None => "never".to_string(),
+                Some(i) => format!("{}s ago", i.elapsed().as_secs()),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_after_stop_are_left_out() {
+        let key = "test:stopped";
+        assert!(record_line(key, 10));
+        set_state(key, StreamState::Stopped);
+        assert!(!record_line(key, 10));
+        let sources = SOURCES.lock().unwrap();
+        let stats = sources.get(key).unwrap();
+        assert!(matches!(stats.state, StreamState::Stopped));
+        assert_eq!(stats.lines, 1);
+    }
+
+    #[test]
+    fn retrying_shows_the_seconds_left() {
+        let key = "test:retrying";
+        set_state(
+            key,
+            StreamState::Retrying(Instant::now() + std::time::Duration::from_secs(30)),
+        );
+        let status = snapshot().into_iter().find(|s| s.key == key).unwrap();
+        assert!(["retrying in 29s", "retrying in 30s"].contains(&status.state.as_str()));
+    }
+}
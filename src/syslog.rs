@@ -0,0 +1,425 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::net::{TcpListener, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use serde_json::{Map, Value};
+
+use crate::index::CommandMessage;
+use crate::ingest::keep;
+use crate::sources::{record_line, set_state, StreamState};
+
+pub const SYSLOG_PORT: u16 = 5514;
+
+/// Longest message read, longer newline-delimited messages are cut and longer
+/// octet-counted frames close the connection.
+pub const MAX_FRAME: usize = 64 * 1024;
+
+/// Waited after a failed receive, so a socket that keeps failing does not spin.
+pub const ERROR_PAUSE: Duration = Duration::from_secs(1);
+
+/// The address to listen on, only this machine unless started with
+/// `--listen-all` as the receivers take documents without authentication.
+pub fn address(port: u16) -> String {
+    match std::env::args().any(|a| a == "--listen-all") {
+        true => format!("0.0.0.0:{}", port),
+        false => format!("127.0.0.1:{}", port),
+    }
+}
+
+const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+pub const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Listens for syslog messages over UDP, one message per datagram, and TCP with
+/// either octet-counted or newline-delimited framing.
+pub fn syslog_listener(tx_send: Sender<CommandMessage>) {
+    let udp_key = "syslog:udp";
+    match UdpSocket::bind(address(SYSLOG_PORT)) {
+        Ok(socket) => {
+            let sender = tx_send.clone();
+            set_state(udp_key, StreamState::Connecting);
+            thread::spawn(move || {
+                let mut buf = vec![0; 65536];
+                loop {
+                    let n = match socket.recv_from(&mut buf) {
+                        Ok((n, _)) => n,
+                        Err(e) => {
+                            set_state(udp_key, StreamState::Error(e.to_string()));
+                            thread::sleep(ERROR_PAUSE);
+                            continue;
+                        }
+                    };
+                    let message = String::from_utf8_lossy(&buf[..n]);
+                    if send(message.trim_end(), udp_key, &sender).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Err(e) => set_state(udp_key, StreamState::Error(e.to_string())),
+    }
+
+    let tcp_key = "syslog:tcp";
+    let listener = match TcpListener::bind(address(SYSLOG_PORT)) {
+        Ok(l) => l,
+        Err(e) => {
+            set_state(tcp_key, StreamState::Error(e.to_string()));
+            return;
+        }
+    };
+    set_state(tcp_key, StreamState::Connecting);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    set_state(tcp_key, StreamState::Error(e.to_string()));
+                    continue;
+                }
+            };
+            let sender = tx_send.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream);
+                while let Ok(Some(frame)) = read_frame(&mut reader) {
+                    let message = String::from_utf8_lossy(&frame);
+                    if send(message.trim_end(), tcp_key, &sender).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn send(message: &str, key: &str, sender: &Sender<CommandMessage>) -> Result<(), ()> {
+    if message.is_empty() {
+        return Ok(());
+    }
+    if !record_line(key, message.len()) {
+        return Ok(());
+    }
+    let doc = parse(message).to_string();
+    if !keep(key, &doc) {
+        return Ok(());
+    }
+    sender
+        .send(CommandMessage::InsertJson(key.to_string(), doc, vec![]))
+        .map_err(|_| ())
+}
+
+/// Reads one message, octet-counted (`LEN SP MSG`) when the frame starts with a
+/// digit and newline-delimited otherwise. `None` at end of stream.
+fn read_frame(reader: &mut impl BufRead) -> std::io::Result<Option<Vec<u8>>> {
+    let first = match reader.fill_buf()?.first() {
+        None => return Ok(None),
+        Some(b) => *b,
+    };
+    let mut frame = vec![];
+    if first.is_ascii_digit() {
+        reader.by_ref().take(8).read_until(b' ', &mut frame)?;
+        let len = String::from_utf8_lossy(&frame)
+            .trim()
+            .parse::<usize>()
+            .unwrap_or(0);
+        if len > MAX_FRAME {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("frame of {} bytes", len),
+            ));
+        }
+        frame = vec![0; len];
+        reader.read_exact(&mut frame)?;
+    } else {
+        reader
+            .by_ref()
+            .take(MAX_FRAME as u64)
+            .read_until(b'\n', &mut frame)?;
+        if frame.len() == MAX_FRAME && frame.last() != Some(&b'\n') {
+            skip_past(reader, b'\n')?;
+        }
+    }
+    Ok(Some(frame))
+}
+
+/// Skips the rest of a frame that was too long, up to and including the
+/// delimiter.
+pub fn skip_past(reader: &mut impl BufRead, delimiter: u8) -> std::io::Result<()> {
+    loop {
+        let (done, used) = {
+            let buf = reader.fill_buf()?;
+            match buf.iter().position(|b| *b == delimiter) {
+                Some(i) => (true, i + 1),
+                None => (buf.is_empty(), buf.len()),
+            }
+        };
+        reader.consume(used);
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Parses an RFC 5424 or RFC 3164 message into a document. Anything that does
+/// not parse ends up in `message` as it was received.
+pub fn parse(message: &str) -> Value {
+    let mut map = Map::new();
+    let (pri, rest) = match parse_pri(message) {
+        Some((pri, rest)) => (pri, rest),
+        // RFC 3164 4.3.3, a message without PRI gets user.notice
+        None => (13, message),
+    };
+    let facility = (pri / 8) as usize;
+    let severity = (pri % 8) as usize;
+    map.insert(
+        "facility".to_string(),
+        Value::from(*FACILITIES.get(facility).unwrap_or(&"unknown")),
+    );
+    map.insert("facility_code".to_string(), Value::from(facility));
+    map.insert("severity".to_string(), Value::from(SEVERITIES[severity]));
+    map.insert("severity_code".to_string(), Value::from(severity));
+
+    match rest.strip_prefix("1 ") {
+        Some(rest) => parse_5424(rest, &mut map),
+        None => parse_3164(rest, &mut map),
+    }
+    Value::Object(map)
+}
+
+fn parse_pri(message: &str) -> Option<(u32, &str)> {
+    let rest = message.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    if end == 0 || end > 3 {
+        return None;
+    }
+    let pri = rest[..end].parse::<u32>().ok()?;
+    match pri < 192 {
+        true => Some((pri, &rest[end + 1..])),
+        false => None,
+    }
+}
+
+fn parse_5424(rest: &str, map: &mut Map<String, Value>) {
+    let mut rest = rest;
+    for field in ["timestamp", "host", "app_name", "proc_id", "msg_id"] {
+        let (value, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+        if value != "-" && !value.is_empty() {
+            map.insert(field.to_string(), Value::from(value));
+        }
+        rest = tail;
+    }
+    let rest = match rest.strip_prefix('-') {
+        Some(tail) => tail,
+        None => {
+            let (structured_data, tail) = parse_structured_data(rest);
+            if !structured_data.is_empty() {
+                map.insert(
+                    "structured_data".to_string(),
+                    Value::Object(structured_data),
+                );
+            }
+            tail
+        }
+    };
+    let message = rest.strip_prefix(' ').unwrap_or(rest);
+    let message = message.strip_prefix('\u{feff}').unwrap_or(message);
+    map.insert("message".to_string(), Value::from(message));
+}
+
+/// Parses `[id param="value" ...]...` into an object per element id.
+fn parse_structured_data(s: &str) -> (Map<String, Value>, &str) {
+    let mut elements = Map::new();
+    let mut rest = s;
+    while let Some(element) = rest.strip_prefix('[') {
+        let (id, mut tail) = match element.find([' ', ']']) {
+            None => break,
+            Some(i) => (&element[..i], &element[i..]),
+        };
+        let mut params = Map::new();
+        loop {
+            tail = tail.trim_start_matches(' ');
+            if let Some(t) = tail.strip_prefix(']') {
+                tail = t;
+                break;
+            }
+            let (name, value) = match tail.split_once("=\"") {
+                None => {
+                    tail = "";
+                    break;
+                }
+                Some(p) => p,
+            };
+            let mut unescaped = String::new();
+            let mut chars = value.char_indices();
+            let mut end = value.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some((_, e)) if e == '"' || e == '\\' || e == ']' => unescaped.push(e),
+                        Some((_, e)) => {
+                            unescaped.push('\\');
+                            unescaped.push(e);
+                        }
+                        None => {}
+                    },
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => unescaped.push(c),
+                }
+            }
+            params.insert(name.to_string(), Value::from(unescaped));
+            tail = &value[end..];
+        }
+        elements.insert(id.to_string(), Value::Object(params));
+        rest = tail;
+    }
+    (elements, rest)
+}
+
+fn parse_3164(rest: &str, map: &mut Map<String, Value>) {
+    let mut rest = rest;
+    if is_3164_timestamp(rest) {
+        map.insert("timestamp".to_string(), Value::from(&rest[..15]));
+        rest = rest[15..].trim_start();
+        if let Some((host, tail)) = rest.split_once(' ') {
+            if !host.ends_with(':') && !host.contains('[') {
+                map.insert("host".to_string(), Value::from(host));
+                rest = tail;
+            }
+        }
+    }
+    let tag_end = rest
+        .find(|c: char| !(c.is_alphanumeric() || "-_./".contains(c)))
+        .unwrap_or(0);
+    let after_tag = &rest[tag_end..];
+    if tag_end > 0 && tag_end <= 48 && (after_tag.starts_with(':') || after_tag.starts_with('[')) {
+        map.insert("app_name".to_string(), Value::from(&rest[..tag_end]));
+        let mut after_tag = after_tag;
+        if let Some(pid) = after_tag.strip_prefix('[') {
+            if let Some((pid, tail)) = pid.split_once(']') {
+                map.insert("proc_id".to_string(), Value::from(pid));
+                after_tag = tail;
+            }
+        }
+        rest = after_tag.strip_prefix(':').unwrap_or(after_tag);
+        rest = rest.strip_prefix(' ').unwrap_or(rest);
+    }
+    map.insert("message".to_string(), Value::from(rest));
+}
+
+/// `Mmm dd hh:mm:ss`, the day padded with a space.
+fn is_3164_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 15
+        && s.is_char_boundary(15)
+        && MONTHS.iter().any(|m| m.as_bytes() == &b[..3])
+        && b[3] == b' '
+        && (b[4] == b' ' || b[4].is_ascii_digit())
+        && b[5].is_ascii_digit()
+        && b[6] == b' '
+        && b[9] == b':'
+        && b[12] == b':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rfc5424() {
+        let doc = parse(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"App\\\"lication\"] An application event",
+        );
+        assert_eq!(doc["facility"], "local4");
+        assert_eq!(doc["severity"], "notice");
+        assert_eq!(doc["timestamp"], "2003-10-11T22:14:15.003Z");
+        assert_eq!(doc["host"], "mymachine.example.com");
+        assert_eq!(doc["app_name"], "evntslog");
+        assert!(doc.get("proc_id").is_none());
+        assert_eq!(doc["msg_id"], "ID47");
+        assert_eq!(doc["structured_data"]["exampleSDID@32473"]["iut"], "3");
+        assert_eq!(
+            doc["structured_data"]["exampleSDID@32473"]["eventSource"],
+            "App\"lication"
+        );
+        assert_eq!(doc["message"], "An application event");
+    }
+
+    #[test]
+    fn parses_rfc3164() {
+        let doc = parse("<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed for lonvick");
+        assert_eq!(doc["facility"], "auth");
+        assert_eq!(doc["severity"], "crit");
+        assert_eq!(doc["timestamp"], "Oct 11 22:14:15");
+        assert_eq!(doc["host"], "mymachine");
+        assert_eq!(doc["app_name"], "su");
+        assert_eq!(doc["proc_id"], "123");
+        assert_eq!(doc["message"], "'su root' failed for lonvick");
+    }
+
+    #[test]
+    fn keeps_unparsable_messages() {
+        let doc = parse("no pri here");
+        assert_eq!(doc["facility"], "user");
+        assert_eq!(doc["severity"], "notice");
+        assert_eq!(doc["message"], "no pri here");
+    }
+
+    #[test]
+    fn reads_both_framings() {
+        let mut reader = "11 <13>1 - - -\n<13>hello\n".as_bytes();
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"<13>1 - - -");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"\n");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"<13>hello\n");
+        assert!(read_frame(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_huge_frames() {
+        let mut reader = "99999999999 <13>hello".as_bytes();
+        assert!(read_frame(&mut reader).is_err());
+    }
+
+    #[test]
+    fn cuts_long_lines() {
+        let long = format!("{}\n<13>next\n", "a".repeat(MAX_FRAME * 2));
+        let mut reader = BufReader::with_capacity(1024, long.as_bytes());
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap().len(), MAX_FRAME);
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"<13>next\n");
+    }
+}
@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::index::rfc3339;
+
+/// One step of the pipeline documents go through before they are indexed.
+/// Fields are addressed with JSON pointers, `/kubernetes/pod`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Transform {
+    /// Moves a field, creating the objects on the way.
+    Rename {
+        from: String,
+        to: String,
+    },
+    Drop {
+        pointer: String,
+    },
+    /// Adds a constant, a field the document already has is kept.
+    Add {
+        pointer: String,
+        value: Value,
+    },
+    /// Replaces a string holding JSON, a logged request body, with the value.
+    ParseJson {
+        pointer: String,
+    },
+    /// Replaces epoch milliseconds with an RFC 3339 timestamp.
+    EpochMillis {
+        pointer: String,
+    },
+}
+
+impl Transform {
+    fn pointers(&self) -> Vec<&str> {
+        match self {
+            Transform::Rename { from, to } => vec![from, to],
+            Transform::Drop { pointer }
+            | Transform::Add { pointer, .. }
+            | Transform::ParseJson { pointer }
+            | Transform::EpochMillis { pointer } => vec![pointer],
+        }
+    }
+
+    fn apply(&self, doc: &mut Value) {
+        match self {
+            Transform::Rename { from, to } => {
+                if let Some(v) = remove(doc, from) {
+                    insert(doc, to, v);
+                }
+            }
+            Transform::Drop { pointer } => {
+                remove(doc, pointer);
+            }
+            Transform::Add { pointer, value } => {
+                if doc.pointer(pointer).is_none() {
+                    insert(doc, pointer, value.clone());
+                }
+            }
+            Transform::ParseJson { pointer } => {
+                if let Some(v) = doc.pointer_mut(pointer) {
+                    if let Some(parsed) = v
+                        .as_str()
+                        .and_then(|s| serde_json::from_str::<Value>(s.trim()).ok())
+                    {
+                        *v = parsed;
+                    }
+                }
+            }
+            Transform::EpochMillis { pointer } => {
+                if let Some(v) = doc.pointer_mut(pointer) {
+                    let millis = match v {
+                        Value::Number(n) => n.to_string(),
+                        Value::String(s) => s.trim().to_string(),
+                        _ => return,
+                    };
+                    // Whole millis stay exact, fractions are kept to the microsecond.
+                    let nanos = match millis.parse::<i64>() {
+                        Ok(m) => Some(m as i128 * 1_000_000),
+                        Err(_) => millis
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|m| m.is_finite())
+                            .map(|m| (m * 1000.0).round() as i128 * 1000),
+                    };
+                    if let Some(nanos) = nanos {
+                        *v = Value::from(rfc3339(nanos));
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn validate(transforms: &[Transform]) -> Result<(), String> {
+    for (i, transform) in transforms.iter().enumerate() {
+        if let Some(p) = transform.pointers().iter().find(|p| !p.starts_with('/')) {
+            return Err(format!(
+                "transform {}: pointer {:?} must start with /",
+                i + 1,
+                p
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs the transforms in order on a JSON document, lines that are not JSON
+/// objects are left as they are.
+pub fn apply(transforms: &[Transform], doc: String) -> String {
+    if transforms.is_empty() {
+        return doc;
+    }
+    let mut value = match serde_json::from_str::<Value>(&doc) {
+        Ok(v @ Value::Object(_)) => v,
+        _ => return doc,
+    };
+    transforms.iter().for_each(|t| t.apply(&mut value));
+    value.to_string()
+}
+
+fn tokens(pointer: &str) -> Vec<String> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+fn remove(doc: &mut Value, pointer: &str) -> Option<Value> {
+    let mut tokens = tokens(pointer);
+    let last = tokens.pop()?;
+    let mut parent = doc;
+    for token in tokens {
+        parent = match parent {
+            Value::Object(map) => map.get_mut(&token)?,
+            Value::Array(array) => array.get_mut(token.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    match parent {
+        Value::Object(map) => map.remove(&last),
+        Value::Array(array) => match last.parse::<usize>() {
+            Ok(i) if i < array.len() => Some(array.remove(i)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Sets the value, replacing anything that is not an object on the way.
+fn insert(doc: &mut Value, pointer: &str, value: Value) {
+    let mut tokens = tokens(pointer);
+    let last = match tokens.pop() {
+        None => return,
+        Some(l) => l,
+    };
+    let mut parent = doc;
+    for token in tokens {
+        if !parent.is_object() {
+            *parent = Value::Object(Map::new());
+        }
+        parent = parent
+            .as_object_mut()
+            .unwrap()
+            .entry(token)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if !parent.is_object() {
+        *parent = Value::Object(Map::new());
+    }
+    parent.as_object_mut().unwrap().insert(last, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(transforms: Value, doc: Value) -> Value {
+        let transforms = serde_json::from_value::<Vec<Transform>>(transforms).unwrap();
+        serde_json::from_str(&apply(&transforms, doc.to_string())).unwrap()
+    }
+
+    #[test]
+    fn renames_creating_objects() {
+        let doc = run(
+            json!([{"op": "rename", "from": "/pod", "to": "/kubernetes/pod"}]),
+            json!({"pod": "web", "kubernetes": 1}),
+        );
+        assert_eq!(doc, json!({"kubernetes": {"pod": "web"}}));
+        let doc = run(
+            json!([{"op": "rename", "from": "/nope", "to": "/b"}]),
+            json!({"a": 1}),
+        );
+        assert_eq!(doc, json!({"a": 1}));
+    }
+
+    #[test]
+    fn drops_fields_and_array_items() {
+        let doc = run(
+            json!([{"op": "drop", "pointer": "/a/b"}, {"op": "drop", "pointer": "/c/0"}]),
+            json!({"a": {"b": 1, "d": 2}, "c": [1, 2]}),
+        );
+        assert_eq!(doc, json!({"a": {"d": 2}, "c": [2]}));
+    }
+
+    #[test]
+    fn adds_missing_fields_only() {
+        let add = json!([{"op": "add", "pointer": "/env/name", "value": "prod"}]);
+        assert_eq!(
+            run(add.clone(), json!({})),
+            json!({"env": {"name": "prod"}})
+        );
+        assert_eq!(
+            run(add, json!({"env": {"name": "dev"}})),
+            json!({"env": {"name": "dev"}})
+        );
+    }
+
+    #[test]
+    fn parses_json_strings() {
+        let parse = json!([{"op": "parse_json", "pointer": "/body"}]);
+        assert_eq!(
+            run(parse.clone(), json!({"body": " {\"a\": 1} "})),
+            json!({"body": {"a": 1}})
+        );
+        assert_eq!(
+            run(parse, json!({"body": "not json"})),
+            json!({"body": "not json"})
+        );
+    }
+
+    #[test]
+    fn converts_epoch_millis() {
+        let epoch = json!([{"op": "epoch_millis", "pointer": "/ts"}]);
+        assert_eq!(
+            run(epoch.clone(), json!({"ts": 1500})),
+            json!({"ts": "1970-01-01T00:00:01.500000000Z"})
+        );
+        assert_eq!(
+            run(epoch.clone(), json!({"ts": "0.25"})),
+            json!({"ts": "1970-01-01T00:00:00.000250000Z"})
+        );
+        assert_eq!(run(epoch, json!({"ts": "x"})), json!({"ts": "x"}));
+    }
+
+    #[test]
+    fn leaves_lines_that_are_not_objects() {
+        let transforms = vec![Transform::Drop {
+            pointer: "/a".to_string(),
+        }];
+        assert_eq!(apply(&transforms, "plain a".to_string()), "plain a");
+        assert_eq!(apply(&transforms, "[1]".to_string()), "[1]");
+    }
+
+    #[test]
+    fn rejects_pointers_without_a_slash() {
+        let transforms = vec![
+            Transform::Drop {
+                pointer: "/a".to_string(),
+            },
+            Transform::Rename {
+                from: "/b".to_string(),
+                to: "c".to_string(),
+            },
+        ];
+        assert_eq!(
+            validate(&transforms),
+            Err("transform 2: pointer \"c\" must start with /".to_string())
+        );
+        assert!(validate(&transforms[..1]).is_ok());
+    }
+}
@@ -1,44 +1,179 @@
+use druid::im::Vector;
 use druid::widget::{
-    Checkbox, Container, Controller, Either, LineBreaking, Painter, RawLabel, Scroll, Slider, Split,
+    Checkbox, Container, Controller, CrossAxisAlignment, Either, LineBreaking, Painter, RawLabel,
+    Scroll, SizedBox, Slider, Split, ViewSwitcher,
 };
 use druid::{
-    theme,
+    commands, lens, theme,
     widget::TextBox,
     widget::{Button, Flex, Label, List},
-    Color, Env, Event, EventCtx, FontDescriptor, FontFamily, RenderContext, Widget, WidgetExt,
+    Application, Color, Env, Event, EventCtx, FileDialogOptions, FileSpec, FontDescriptor,
+    FontFamily, HotKey, KbKey, PaintCtx, RenderContext, SysMods, UpdateCtx, Widget, WidgetExt,
+    WidgetId,
 };
 
 use crate::data::*;
 use crate::delegate::{
     CHANGE_SETTINGS, CHECK_CLICKED_FOR_POINTER, CHECK_CLICKED_FOR_POINTER_SORT,
-    CHECK_CLICKED_FOR_POINTER_VIEW, CLEAR_DB, SEARCH, TAIL,
+    CHECK_CLICKED_FOR_POINTER_VIEW, CHECK_CLICKED_FOR_POINTER_VOLATILE, CLEAR_DB, PATTERN_FILTER,
+    SEARCH, TAIL,
 };
 use crate::index::CommandMessage;
+use crate::ingest;
+use crate::level::Level;
 use crate::GLOBAL_STATE;
 
+const QUERY: WidgetId = WidgetId::reserved(1);
+const NOT_QUERY: WidgetId = WidgetId::reserved(2);
+
+/// Results moved by page up and page down.
+const PAGE: isize = 20;
+
+/// Width of the tick in front of a result.
+const TICK: f64 = 28.0;
+
 fn new_search_textbox() -> impl Widget<AppState> {
     let new_search_textbox = TextBox::new()
         .with_placeholder("Filter documents")
         .expand_width()
         .lens(AppState::query)
-        .controller(SearchController);
+        .controller(SearchController)
+        .with_id(QUERY);
     let new_search_textbox_neq = TextBox::new()
         .with_placeholder("Filter away documents")
         .expand_width()
         .lens(AppState::not_query)
-        .controller(ControllerForNegSearch);
+        .controller(ControllerForNegSearch)
+        .with_id(NOT_QUERY);
 
     Flex::row()
         .with_flex_child(new_search_textbox.padding(5.), 1.)
         .with_flex_child(new_search_textbox_neq.padding(5.), 1.)
         .with_child(Checkbox::new("Exact").lens(AppState::exact))
-        .on_click(|ctx, data: &mut AppState, _env| {
-            GLOBAL_STATE.lock().unwrap().exact = data.exact;
-            ctx.submit_command(SEARCH.with((
-                (data.query.to_string(), data.not_query.to_string()),
-                GLOBAL_STATE.lock().unwrap().exact,
-            )));
-        })
+        .with_child(
+            Checkbox::new("Collapse")
+                .lens(AppState::collapse)
+                .padding((5., 0.)),
+        )
+        .with_child(Checkbox::new("Table").lens(AppState::table))
+        .on_click(|ctx, data: &mut AppState, _env| search_exact(ctx, data))
+}
+
+fn search_exact(ctx: &mut EventCtx, data: &mut AppState) {
+    GLOBAL_STATE.lock().unwrap().exact = data.exact;
+    ctx.submit_command(SEARCH.with((
+        (data.query.to_string(), data.not_query.to_string()),
+        GLOBAL_STATE.lock().unwrap().exact,
+    )));
+}
+
+fn toggle_tail(ctx: &mut EventCtx, app_state: &mut AppState) {
+    app_state.tail = !app_state.tail;
+    GLOBAL_STATE.lock().unwrap().query = app_state.query.to_string();
+    GLOBAL_STATE.lock().unwrap().query_neg = app_state.not_query.to_string();
+    GLOBAL_STATE.lock().unwrap().exact = app_state.exact;
+    ctx.submit_command(TAIL.with(app_state.tail));
+}
+
+/// Asks where to write every match of the query to, the delegate starts the
+/// export once a file is chosen.
+fn export(ctx: &mut EventCtx, spec: FileSpec) {
+    ctx.submit_command(
+        commands::SHOW_SAVE_PANEL.with(
+            FileDialogOptions::new()
+                .allowed_types(vec![spec])
+                .default_type(spec)
+                .default_name(format!("melt.{}", spec.extensions[0])),
+        ),
+    );
+}
+
+/// Keyboard shortcuts for the whole window.
+struct Shortcuts;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for Shortcuts {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        if let Event::KeyDown(key) = event {
+            let handled = if data.settings {
+                if HotKey::new(None, KbKey::Escape).matches(key)
+                    || HotKey::new(SysMods::Cmd, ",").matches(key)
+                {
+                    data.persist();
+                    ctx.submit_command(CHANGE_SETTINGS.with(false));
+                    true
+                } else {
+                    false
+                }
+            } else if HotKey::new(None, KbKey::ArrowDown).matches(key) {
+                data.move_selection(ctx, 1);
+                true
+            } else if HotKey::new(None, KbKey::ArrowUp).matches(key) {
+                data.move_selection(ctx, -1);
+                true
+            } else if HotKey::new(None, KbKey::PageDown).matches(key) {
+                data.move_selection(ctx, PAGE);
+                true
+            } else if HotKey::new(None, KbKey::PageUp).matches(key) {
+                data.move_selection(ctx, -PAGE);
+                true
+            } else if HotKey::new(SysMods::Cmd, "f").matches(key) {
+                ctx.set_focus(QUERY);
+                true
+            } else if HotKey::new(SysMods::CmdShift, "F").matches(key) {
+                ctx.set_focus(NOT_QUERY);
+                true
+            } else if HotKey::new(SysMods::Cmd, "e").matches(key) {
+                data.exact = !data.exact;
+                search_exact(ctx, data);
+                true
+            } else if HotKey::new(SysMods::Cmd, "t").matches(key) {
+                toggle_tail(ctx, data);
+                true
+            } else if HotKey::new(SysMods::CmdShift, "C").matches(key) {
+                if let Some(item) = data.selected.and_then(|i| data.items_rich.get(i)) {
+                    Application::global().clipboard().put_string(&item.text);
+                }
+                true
+            } else if HotKey::new(SysMods::Cmd, ",").matches(key) {
+                ctx.submit_command(CHANGE_SETTINGS.with(true));
+                true
+            } else {
+                false
+            };
+            if handled {
+                ctx.set_handled();
+                return;
+            }
+        }
+
+        child.event(ctx, event, data, env)
+    }
+}
+
+/// Scrolls the result moved to with the keyboard into view.
+struct ScrollToSelected;
+
+impl<W: Widget<ItemRich>> Controller<ItemRich, W> for ScrollToSelected {
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &ItemRich,
+        data: &ItemRich,
+        env: &Env,
+    ) {
+        if data.selected && !old_data.selected {
+            ctx.scroll_to_view();
+        }
+        child.update(ctx, old_data, data, env)
+    }
 }
 
 struct SearchController;
@@ -91,12 +226,42 @@ impl<W: Widget<AppState>> Controller<AppState, W> for ControllerForNegSearch {
     }
 }
 
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::rgb8(0xF0, 0x5A, 0x5A),
+        Level::Warn => Color::rgb8(0xF0, 0xB4, 0x3C),
+        Level::Info => Color::rgb8(0x78, 0xB4, 0xF0),
+        Level::Debug => Color::rgb8(0x96, 0x96, 0x96),
+    }
+}
+
+/// Tints errors and warnings and marks every row with a level at its left edge.
+fn paint_level(ctx: &mut PaintCtx, level: Option<Level>) {
+    if let Some(level) = level {
+        let bounds = ctx.size().to_rect();
+        if level == Level::Error || level == Level::Warn {
+            ctx.fill(bounds, &level_color(level).with_alpha(0.12));
+        }
+        ctx.fill(
+            bounds.with_size((3.0, bounds.height())),
+            &level_color(level),
+        );
+    }
+}
+
 fn documents() -> impl Widget<ItemRich> {
-    let painter = Painter::new(|ctx, _, env| {
+    let painter = Painter::new(|ctx, data: &ItemRich, env| {
         let bounds = ctx.size().to_rect();
 
         ctx.fill(bounds, &env.get(theme::BACKGROUND_DARK));
 
+        paint_level(ctx, data.level);
+
+        if data.selected {
+            ctx.fill(bounds, &Color::rgb8(0x3a, 0x3a, 0x3a));
+            ctx.stroke(bounds.inset(-0.5), &env.get(theme::PRIMARY_LIGHT), 1.0);
+        }
+
         if ctx.is_hot() {
             ctx.stroke(bounds.inset(-0.5), &Color::WHITE, 1.0);
         }
@@ -110,9 +275,397 @@ fn documents() -> impl Widget<ItemRich> {
         .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
         .with_line_break_mode(LineBreaking::WordWrap)
         .expand_width()
-        .background(painter)
         .on_click(ItemRich::click_view);
-    label
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Checkbox::new("").lens(ItemRich::checked).fix_width(TICK))
+        .with_flex_child(label, 1.)
+        .background(painter)
+        .controller(ScrollToSelected)
+}
+
+/// The resolved value of the pointer, cut at the truncation of the column.
+fn cell(item: &ItemRich, column: &Column) -> String {
+    let value = item
+        .pointer_states
+        .iter()
+        .find(|p| p.text == column.pointer && p.resolved != p.text)
+        .map(|p| p.resolved.as_str())
+        .unwrap_or("");
+    let truncate = column.truncate as usize;
+    match truncate > 0 && value.chars().count() > truncate {
+        true => format!("{}…", value.chars().take(truncate).collect::<String>()),
+        false => value.to_string(),
+    }
+}
+
+fn table_header() -> impl Widget<AppState> {
+    ViewSwitcher::new(
+        |data: &AppState, _env| data.columns.clone(),
+        |columns, _data, _env| {
+            let mut row = Flex::row().with_child(SizedBox::empty().width(TICK));
+            for (i, column) in columns.iter().enumerate() {
+                let pointer = column.pointer.to_string();
+                let title = match column.sorted {
+                    true => format!("{} ▼", column.pointer),
+                    false => column.pointer.to_string(),
+                };
+                let truncate = match column.truncate as usize {
+                    0 => "wrap".to_string(),
+                    n => format!("max {}", n),
+                };
+                row.add_child(
+                    Flex::column()
+                        .with_child(
+                            Label::new(title)
+                                .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                                .with_text_color(Color::rgb8(0xFF, 0xB4, 0x5A))
+                                .with_line_break_mode(LineBreaking::Clip)
+                                .on_click(move |ctx, data: &mut AppState, env| {
+                                    let sorted = data
+                                        .columns
+                                        .iter()
+                                        .any(|c| c.sorted && c.pointer == pointer);
+                                    match sorted {
+                                        true => data.sort_by(""),
+                                        false => data.sort_by(&pointer),
+                                    }
+                                    AppState::click_search(ctx, data, env);
+                                })
+                                .align_left(),
+                        )
+                        .with_child(
+                            Flex::row()
+                                .with_child(Button::new("<").on_click(
+                                    move |ctx, data: &mut AppState, env| {
+                                        data.move_column(i, false);
+                                        AppState::click_search(ctx, data, env);
+                                    },
+                                ))
+                                .with_child(Button::new(">").on_click(
+                                    move |ctx, data: &mut AppState, env| {
+                                        data.move_column(i, true);
+                                        AppState::click_search(ctx, data, env);
+                                    },
+                                ))
+                                .with_child(Button::new("-").on_click(
+                                    move |_ctx, data: &mut AppState, _env| {
+                                        data.columns[i].width =
+                                            (data.columns[i].width - 40.0).max(40.0);
+                                    },
+                                ))
+                                .with_child(Button::new("+").on_click(
+                                    move |_ctx, data: &mut AppState, _env| {
+                                        data.columns[i].width += 40.0;
+                                    },
+                                ))
+                                .align_left(),
+                        )
+                        .with_child(
+                            Flex::row()
+                                .with_child(Button::new("cut -").on_click(
+                                    move |_ctx, data: &mut AppState, _env| {
+                                        data.columns[i].truncate =
+                                            (data.columns[i].truncate - 10.0).max(0.0);
+                                    },
+                                ))
+                                .with_child(Button::new("cut +").on_click(
+                                    move |_ctx, data: &mut AppState, _env| {
+                                        data.columns[i].truncate += 10.0;
+                                    },
+                                ))
+                                .with_child(Label::new(truncate))
+                                .align_left(),
+                        )
+                        .fix_width(column.width)
+                        .padding((0., 0., 8., 5.)),
+                );
+            }
+            row.align_left().boxed()
+        },
+    )
+}
+
+fn table_row() -> impl Widget<(Vector<Column>, ItemRich)> {
+    let painter = Painter::new(|ctx, data: &(Vector<Column>, ItemRich), env| {
+        let bounds = ctx.size().to_rect();
+
+        ctx.fill(bounds, &env.get(theme::BACKGROUND_DARK));
+
+        paint_level(ctx, data.1.level);
+
+        if data.1.selected {
+            ctx.fill(bounds, &Color::rgb8(0x3a, 0x3a, 0x3a));
+            ctx.stroke(bounds.inset(-0.5), &env.get(theme::PRIMARY_LIGHT), 1.0);
+        }
+
+        if ctx.is_hot() {
+            ctx.stroke(bounds.inset(-0.5), &Color::WHITE, 1.0);
+        }
+    });
+
+    ViewSwitcher::new(
+        |data: &(Vector<Column>, ItemRich), _env| data.0.clone(),
+        |columns, _data, _env| {
+            let mut row = Flex::row()
+                .cross_axis_alignment(CrossAxisAlignment::Start)
+                .with_child(
+                    Checkbox::new("")
+                        .lens(lens::Map::new(
+                            |data: &(Vector<Column>, ItemRich)| data.1.checked,
+                            |data: &mut (Vector<Column>, ItemRich), checked| {
+                                data.1.checked = checked
+                            },
+                        ))
+                        .fix_width(TICK),
+                );
+            for column in columns.iter() {
+                let c = column.clone();
+                row.add_child(
+                    Label::new(move |data: &(Vector<Column>, ItemRich), _env: &_| {
+                        cell(&data.1, &c)
+                    })
+                    .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                    .with_line_break_mode(match column.truncate > 0.0 {
+                        true => LineBreaking::Clip,
+                        false => LineBreaking::WordWrap,
+                    })
+                    .fix_width(column.width)
+                    .padding((0., 0., 8., 0.)),
+                );
+            }
+            row.boxed()
+        },
+    )
+    .expand_width()
+    .background(painter)
+    .on_click(|ctx, data: &mut (Vector<Column>, ItemRich), env| {
+        ItemRich::click_view(ctx, &mut data.1, env)
+    })
+}
+
+fn table() -> impl Widget<AppState> {
+    Scroll::new(
+        Flex::column()
+            .with_child(table_header())
+            .with_child(List::new(table_row).lens(lens::Map::new(
+                |data: &AppState| (data.columns.clone(), data.items_rich.clone()),
+                |data: &mut AppState, rows: (Vector<Column>, Vector<ItemRich>)| {
+                    data.items_rich = rows.1
+                },
+            )))
+            .cross_axis_alignment(CrossAxisAlignment::Start),
+    )
+}
+
+fn tree_node() -> impl Widget<TreeNode> {
+    Either::new(
+        |node: &TreeNode, _env| node.hidden,
+        SizedBox::empty(),
+        Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_child(
+                Label::dynamic(|node: &TreeNode, _| {
+                    match (node.container, node.expanded) {
+                        (false, _) => "  ",
+                        (true, true) => "▼ ",
+                        (true, false) => "▶ ",
+                    }
+                    .to_string()
+                })
+                .with_font(FontDescriptor::new(FontFamily::MONOSPACE)),
+            )
+            .with_flex_child(
+                RawLabel::new()
+                    .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .lens(TreeNode::text)
+                    .expand_width()
+                    .on_click(TreeNode::click_toggle),
+                1.,
+            )
+            .with_child(Button::new("Copy").on_click(TreeNode::click_copy_value))
+            .with_child(Button::new("Pointer").on_click(TreeNode::click_copy_pointer))
+            .with_child(Either::new(
+                |node: &TreeNode, _env| node.container,
+                SizedBox::empty(),
+                Flex::row()
+                    .with_child(Button::new("+").on_click(TreeNode::click_filter))
+                    .with_child(Button::new("-").on_click(TreeNode::click_exclude)),
+            )),
+    )
+}
+
+fn streams() -> impl Widget<AppState> {
+    Scroll::new(List::new(|| {
+        Flex::row()
+            .with_child(Button::new("Stop").on_click(StreamStatus::click_stop))
+            .with_child(Button::new("Restart").on_click(StreamStatus::click_restart))
+            .with_child(
+                Label::new(|item: &StreamStatus, _env: &_| {
+                    format!(
+                        "{}  {}  lines {}  bytes {}  dropped {}  last line {}",
+                        item.key, item.state, item.lines, item.bytes, item.dropped, item.last_line
+                    )
+                })
+                .with_font(FontDescriptor::new(FontFamily::MONOSPACE)),
+            )
+    }))
+    .vertical()
+    .lens(AppState::streams)
+    .fix_height(200.)
+    .align_left()
+}
+
+fn patterns() -> impl Widget<AppState> {
+    Flex::column()
+        .with_child(
+            Label::raw()
+                .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                .lens(AppState::patterns_info)
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder(
+                            "Anomalies since, RFC 3339 or epoch, the last 15 minutes when empty",
+                        )
+                        .expand_width()
+                        .lens(AppState::anomaly_since)
+                        .padding(5.),
+                    1.,
+                )
+                .with_child(
+                    Button::new("Refresh").on_click(|_ctx, data: &mut AppState, _env| {
+                        data.tx
+                            .send(CommandMessage::Patterns(
+                                data.timelimit as u64,
+                                data.anomaly_since.to_string(),
+                            ))
+                            .unwrap();
+                    }),
+                ),
+        )
+        .with_child(
+            Scroll::new(List::new(|| {
+                Flex::row()
+                    .with_child(Button::new("Filter").on_click(AnomalyStatus::click_filter))
+                    .with_child(
+                        Label::new(|item: &AnomalyStatus, _env: &_| {
+                            format!(
+                                "{:>6}  before {:>8}  after {:>8}  {}",
+                                item.change, item.before, item.after, item.template
+                            )
+                        })
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .with_text_color(Color::rgb8(0xE0, 0xA0, 0x40)),
+                    )
+            }))
+            .vertical()
+            .lens(AppState::anomalies)
+            .fix_height(120.)
+            .align_left(),
+        )
+        .with_child(
+            Scroll::new(List::new(|| {
+                Flex::row()
+                    .with_child(Button::new("Filter").on_click(PatternStatus::click_filter))
+                    .with_child(Button::new("Exclude").on_click(PatternStatus::click_exclude))
+                    .with_child(
+                        Label::new(|item: &PatternStatus, _env: &_| {
+                            format!("{:>10}  {}", item.count, item.template)
+                        })
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE)),
+                    )
+            }))
+            .vertical()
+            .lens(AppState::patterns)
+            .fix_height(200.)
+            .align_left(),
+        )
+}
+
+fn context() -> impl Widget<AppState> {
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Label::raw()
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .lens(AppState::context_info),
+                )
+                .with_child(
+                    Button::new("Close").on_click(|_ctx, data: &mut AppState, _env| {
+                        data.context_info.clear();
+                        data.context.clear();
+                    }),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Scroll::new(List::new(|| {
+                Label::raw()
+                    .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .lens(Item::view)
+                    .expand_width()
+                    .on_click(Item::click_view)
+            }))
+            .vertical()
+            .lens(AppState::context)
+            .fix_height(250.)
+            .align_left(),
+        )
+}
+
+fn follow() -> impl Widget<AppState> {
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Label::raw()
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .lens(AppState::follow_info),
+                )
+                .with_child(
+                    Button::new("Close").on_click(|_ctx, data: &mut AppState, _env| {
+                        data.follow_info.clear();
+                        data.follow.clear();
+                    }),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Scroll::new(List::new(|| {
+                Flex::column()
+                    .with_child(
+                        Label::new(|group: &FollowGroup, _env: &_| {
+                            format!("{}  {}", group.name, group.items.len())
+                        })
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .with_text_color(Color::rgb8(0xFF, 0xB4, 0x5A))
+                        .align_left(),
+                    )
+                    .with_child(
+                        List::new(|| {
+                            Label::raw()
+                                .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                                .with_line_break_mode(LineBreaking::WordWrap)
+                                .lens(Item::view)
+                                .expand_width()
+                                .on_click(Item::click_view)
+                        })
+                        .lens(FollowGroup::items)
+                        .padding((20., 0., 0., 5.)),
+                    )
+            }))
+            .vertical()
+            .lens(AppState::follow)
+            .fix_height(250.)
+            .align_left(),
+        )
 }
 
 pub fn build_ui() -> impl Widget<AppState> {
@@ -130,12 +683,52 @@ pub fn build_ui() -> impl Widget<AppState> {
                 )
                 .with_child(
                     Button::new("Pods")
-                        .on_click(|ctx, data: &mut AppState, _env| {
-                            data.tx.send(CommandMessage::Pod).unwrap();
-                            ctx.set_disabled(true);
+                        .on_click(|_ctx, data: &mut AppState, _env| {
+                            let contexts = match data.contexts.is_empty() {
+                                true => vec!["".to_string()],
+                                false => data.selected_contexts(),
+                            };
+                            data.tx.send(CommandMessage::Pod(contexts)).unwrap();
+                        })
+                        .align_left(),
+                )
+                .with_child(
+                    Button::new("Streams")
+                        .on_click(|_ctx, data: &mut AppState, _env| {
+                            data.show_streams = !data.show_streams;
                         })
                         .align_left(),
                 )
+                .with_child(
+                    Button::new("Patterns")
+                        .on_click(|_ctx, data: &mut AppState, _env| {
+                            data.show_patterns = !data.show_patterns;
+                            if data.show_patterns {
+                                data.tx
+                                    .send(CommandMessage::Patterns(
+                                        data.timelimit as u64,
+                                        data.anomaly_since.to_string(),
+                                    ))
+                                    .unwrap();
+                            }
+                        })
+                        .align_left(),
+                )
+                .with_flex_child(
+                    Scroll::new(
+                        List::new(|| {
+                            Flex::row()
+                                .with_child(Checkbox::new("").lens(KubeContext::checked))
+                                .with_child(Label::new(|item: &KubeContext, _env: &_| {
+                                    format!("{}", item.name)
+                                }))
+                        })
+                        .horizontal(),
+                    )
+                    .horizontal()
+                    .lens(AppState::contexts),
+                    1.,
+                )
                 .with_child(
                     Button::new("Clear")
                         .on_click(|ctx, _: &mut AppState, _env| {
@@ -145,6 +738,26 @@ pub fn build_ui() -> impl Widget<AppState> {
                         .align_right(),
                 ),
         )
+        .with_child(
+            Flex::row()
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder("File, directory or glob to tail")
+                        .expand_width()
+                        .lens(AppState::file_pattern)
+                        .padding(5.),
+                    1.,
+                )
+                .with_child(Button::new("Tail file").on_click(
+                    |_ctx, data: &mut AppState, _env| {
+                        if !data.file_pattern.is_empty() {
+                            data.tx
+                                .send(CommandMessage::TailFile(data.file_pattern.to_string()))
+                                .unwrap();
+                        }
+                    },
+                )),
+        )
         .with_child(
             Label::raw()
                 .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
@@ -194,29 +807,165 @@ pub fn build_ui() -> impl Widget<AppState> {
         .with_child(
             Checkbox::new("Tail")
                 .lens(AppState::tail)
-                .on_click(|ctx, app_state: &mut AppState, _env| {
-                    app_state.tail = !app_state.tail;
-                    GLOBAL_STATE.lock().unwrap().query = app_state.query.to_string();
-                    GLOBAL_STATE.lock().unwrap().query_neg = app_state.not_query.to_string();
-                    GLOBAL_STATE.lock().unwrap().exact = app_state.exact;
-                    ctx.submit_command(TAIL.with(app_state.tail));
-                })
+                .on_click(|ctx, app_state: &mut AppState, _env| toggle_tail(ctx, app_state))
                 .align_left(),
         )
+        .with_child(Either::new(
+            |data: &AppState, _env| data.show_streams,
+            streams(),
+            Flex::column(),
+        ))
+        .with_child(Either::new(
+            |data: &AppState, _env| data.context_info.is_empty(),
+            Flex::column(),
+            context(),
+        ))
+        .with_child(Either::new(
+            |data: &AppState, _env| data.follow_info.is_empty(),
+            Flex::column(),
+            follow(),
+        ))
+        .with_child(Either::new(
+            |data: &AppState, _env| data.show_patterns,
+            patterns(),
+            Flex::column(),
+        ))
+        .with_child(Either::new(
+            |data: &AppState, _env| data.pattern_filter.is_empty(),
+            Flex::column(),
+            Flex::row()
+                .with_child(
+                    Label::raw()
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .lens(AppState::pattern_filter),
+                )
+                .with_child(Button::new("Clear pattern").on_click(
+                    |ctx, _data: &mut AppState, _env| {
+                        ctx.submit_command(PATTERN_FILTER.with(("".to_string(), false)));
+                    },
+                ))
+                .align_left(),
+        ))
         .with_child(new_search_textbox())
-        .with_flex_child(Scroll::new(items).vertical(), 1.);
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new("Tick all")
+                        .on_click(|_ctx, data: &mut AppState, _env| data.check_all(true)),
+                )
+                .with_child(
+                    Button::new("Untick")
+                        .on_click(|_ctx, data: &mut AppState, _env| data.check_all(false)),
+                )
+                .with_child(Button::new("Copy JSON lines").on_click(AppState::click_copy_lines))
+                .with_child(Button::new("Copy pretty").on_click(AppState::click_copy_pretty))
+                .with_child(Button::new("Copy TSV").on_click(AppState::click_copy_tsv))
+                .with_child(Button::new("Export JSONL").on_click(
+                    |ctx, _data: &mut AppState, _env| {
+                        export(ctx, FileSpec::new("JSON Lines", &["jsonl"]))
+                    },
+                ))
+                .with_child(Button::new("Export CSV").on_click(
+                    |ctx, _data: &mut AppState, _env| export(ctx, FileSpec::new("CSV", &["csv"])),
+                ))
+                .with_child(
+                    Label::raw()
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .lens(AppState::export_info)
+                        .padding((5., 0.)),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Label::new("Levels of the matches looked at").padding((0., 0., 10., 0.)),
+                )
+                .with_child(
+                    List::new(|| {
+                        Flex::row()
+                            .with_child(
+                                Checkbox::new("")
+                                    .lens(LevelChip::checked)
+                                    .on_click(LevelChip::click_toggle),
+                            )
+                            .with_child(
+                                Label::new(|chip: &LevelChip, _env: &_| {
+                                    format!("{} {}", chip.level.name(), chip.count)
+                                })
+                                .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                                .env_scope(
+                                    |env, chip: &LevelChip| {
+                                        env.set(theme::TEXT_COLOR, level_color(chip.level))
+                                    },
+                                ),
+                            )
+                            .padding((0., 0., 10., 0.))
+                    })
+                    .horizontal()
+                    .lens(AppState::levels),
+                )
+                .align_left(),
+        )
+        .with_flex_child(
+            Either::new(
+                |data: &AppState, _env| data.table && !data.columns.is_empty(),
+                table(),
+                Scroll::new(items).vertical(),
+            ),
+            1.,
+        );
 
     let container = Container::new(
         Split::columns(
             flex,
-            Scroll::new(
-                TextBox::multiline()
-                    .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
-                    .with_line_wrapping(true)
-                    .lens(AppState::view)
-                    .expand_width(),
-            )
-            .vertical(),
+            Flex::column()
+                .with_child(
+                    Flex::row()
+                        .with_child(Button::new("Context").on_click(
+                            |_ctx, data: &mut AppState, _env| {
+                                if let Some(number) = data.view_number {
+                                    data.tx
+                                        .send(CommandMessage::Context(
+                                            number,
+                                            data.context_size as usize,
+                                        ))
+                                        .unwrap();
+                                }
+                            },
+                        ))
+                        .with_child(
+                            Slider::new()
+                                .with_range(1.0, 100.0)
+                                .with_step(1.0)
+                                .lens(AppState::context_size),
+                        )
+                        .with_child(Label::dynamic(|data: &AppState, _| {
+                            format!("{} before and after", data.context_size as u64)
+                        }))
+                        .align_left(),
+                )
+                .with_child(
+                    Scroll::new(List::new(|| {
+                        Flex::row()
+                            .with_child(Button::new("Follow").on_click(ViewPointer::click_follow))
+                            .with_child(
+                                Label::new(|item: &ViewPointer, _env: &_| {
+                                    format!("{} = {}", item.pointer, item.value)
+                                })
+                                .with_font(FontDescriptor::new(FontFamily::MONOSPACE)),
+                            )
+                    }))
+                    .vertical()
+                    .lens(AppState::view_pointers)
+                    .fix_height(120.)
+                    .align_left(),
+                )
+                .with_flex_child(
+                    Scroll::new(List::new(tree_node).lens(AppState::tree).expand_width())
+                        .vertical(),
+                    1.,
+                ),
         )
         .split_point(1.)
         .draggable(true)
@@ -238,10 +987,94 @@ pub fn build_ui() -> impl Widget<AppState> {
                 .on_click(|ctx, data: &mut AppState, _env| {
                     data.pointers.clear();
                     data.pointers_view.clear();
+                    data.columns.clear();
                     ctx.request_update();
                 })
                 .align_left(),
         )
+        .with_child(
+            Flex::row()
+                .with_child(Label::new("Level pointer:").padding(8.0))
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder("/level, /severity or the syslog severity when empty")
+                        .expand_width()
+                        .lens(AppState::level_pointer),
+                    1.,
+                ),
+        )
+        .with_child(
+            Label::new("Ingest rules, parsing, multiline events, sampling, transforms, script and redaction:")
+                .padding(8.0)
+                .align_left(),
+        )
+        .with_child(
+            TextBox::multiline()
+                .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                .lens(AppState::ingest_config)
+                .expand_width()
+                .fix_height(160.0),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Button::new("Apply ingest rules").on_click(
+                    |_ctx, data: &mut AppState, _env| {
+                        data.ingest_error = match ingest::set_config_json(&data.ingest_config) {
+                            Ok(_) => {
+                                data.ingest_config = ingest::config_json();
+                                "".to_string()
+                            }
+                            Err(e) => e,
+                        };
+                    },
+                ))
+                .with_child(Button::new("Preview").on_click(
+                    |_ctx, data: &mut AppState, _env| {
+                        match ingest::preview(&data.ingest_config, &data.ingest_sample) {
+                            Ok(p) => {
+                                data.ingest_preview = p;
+                                data.ingest_error = "".to_string();
+                            }
+                            Err(e) => data.ingest_error = e,
+                        }
+                    },
+                ))
+                .with_child(
+                    Label::new(|data: &AppState, _env: &_| data.ingest_error.to_string())
+                        .with_text_color(Color::rgb8(0xE0, 0x60, 0x60)),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Label::new(|data: &AppState, _env: &_| match data.ingest_sample_processed {
+                true => "Sample, the last clicked document as stored, after the rules it was ingested with, and the sample after the rules:",
+                false => "Sample, the line the last clicked document was parsed from or a pasted line, and the sample after the rules:",
+            }.to_string())
+            .padding(8.0)
+            .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_flex_child(
+                    TextBox::multiline()
+                        .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                        .lens(AppState::ingest_sample)
+                        .expand(),
+                    1.0,
+                )
+                .with_flex_child(
+                    Scroll::new(
+                        Label::new(|data: &AppState, _env: &_| data.ingest_preview.to_string())
+                            .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+                            .with_line_break_mode(LineBreaking::WordWrap)
+                            .expand_width(),
+                    )
+                    .vertical()
+                    .expand(),
+                    1.0,
+                )
+                .fix_height(160.0),
+        )
         .with_flex_child(
             Scroll::new(List::new(|| {
                 Flex::row()
@@ -269,6 +1102,17 @@ pub fn build_ui() -> impl Widget<AppState> {
                                 );
                             }),
                     )
+                    .with_child(
+                        Checkbox::new("Volatile")
+                            .lens(PointerState::checked_volatile)
+                            .on_click(|ctx, pointer_state, _env| {
+                                pointer_state.checked_volatile = !pointer_state.checked_volatile;
+                                ctx.submit_command(
+                                    CHECK_CLICKED_FOR_POINTER_VOLATILE
+                                        .with(pointer_state.clone()),
+                                );
+                            }),
+                    )
                     .with_child(Label::new(|item: &PointerState, _env: &_| {
                         format!("{}", item.text)
                     }))
@@ -310,6 +1154,7 @@ pub fn build_ui() -> impl Widget<AppState> {
         |data: &AppState, _env| data.settings,
         flex_settings,
         container,
-    );
+    )
+    .controller(Shortcuts);
     either
 }